@@ -0,0 +1,277 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Loads monitoring profiles from a TOML configuration file
+//
+//--------------------------------------------------------------------
+use crate::procdumpconfiguration::{DumpCompression, ProcDumpConfiguration, validate_configuration};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+
+//
+// Layout of the configuration file. Each [[profile]] table describes one
+// independent monitor, e.g.:
+//
+//   [[profile]]
+//   name = "web"
+//   process_name = "nginx"
+//   wait = true
+//   cpu = 90
+//   number_of_dumps = 3
+//   output_directory = "/var/dumps"
+//
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile
+{
+    #[serde(default)]
+    profile: Vec<ProfileDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileDefinition
+{
+    name: Option<String>,
+
+    // Target selector (exactly one)
+    pid: Option<i32>,
+    pgid: Option<i32>,
+    process_name: Option<String>,
+    #[serde(default)]
+    wait: bool,
+
+    // Triggers
+    cpu: Option<u32>,
+    #[serde(default)]
+    cpu_below: bool,
+    memory: Option<u32>,
+    #[serde(default)]
+    memory_below: bool,
+    threads: Option<u32>,
+    file_descriptors: Option<u32>,
+    signal: Option<u32>,
+
+    polling_frequency: Option<u64>,
+    threshold_seconds: Option<u32>,
+    number_of_dumps: Option<u32>,
+
+    // Output
+    output_directory: Option<String>,
+    dump_name: Option<String>,
+    #[serde(default)]
+    overwrite: bool,
+    #[serde(default)]
+    record_samples: bool,
+    sample_file_size: Option<u64>,
+    #[serde(default)]
+    redact_environment: Vec<String>,
+    #[serde(default)]
+    history_seconds: u32,
+    compression: Option<String>,
+    #[serde(default)]
+    min_free_space: u64,
+    dump_timeout: Option<u64>,
+    max_dumps: Option<u32>,
+    max_total_size: Option<u64>,
+    max_age_hours: Option<u64>,
+
+    // Hooks
+    pre_command: Option<String>,
+    pre_command_timeout: Option<u64>,
+    post_command: Option<String>,
+    post_command_timeout: Option<u64>,
+    #[serde(default)]
+    post_command_wait: bool,
+}
+
+// -----------------------------------------------------------------
+// load_profiles - Reads the configuration file and returns one
+// validated configuration per profile
+// -----------------------------------------------------------------
+pub fn load_profiles(path: &String) -> Result<Vec<ProcDumpConfiguration>, String>
+{
+    let contents = match fs::read_to_string(path)
+    {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("Failed to read configuration file {}: {}", path, e)),
+    };
+
+    let config_file: ConfigFile = match toml::from_str(&contents)
+    {
+        Ok(config_file) => config_file,
+        Err(e) => return Err(format!("Failed to parse configuration file {}: {}", path, e)),
+    };
+
+    if config_file.profile.is_empty()
+    {
+        return Err(format!("No profiles found in configuration file {}.", path));
+    }
+
+    let mut profiles = Vec::new();
+    let mut names = HashSet::new();
+    for (index, definition) in config_file.profile.into_iter().enumerate()
+    {
+        let name = definition.name.clone().unwrap_or_else(|| format!("profile{}", index + 1));
+        if !names.insert(name.clone())
+        {
+            return Err(format!("Duplicate profile name '{}' in configuration file {}.", name, path));
+        }
+
+        let mut config = match profile_to_configuration(&name, definition)
+        {
+            Ok(config) => config,
+            Err(e) => return Err(format!("Invalid profile '{}': {}", name, e)),
+        };
+
+        if validate_configuration(&mut config) < 0
+        {
+            return Err(format!("Invalid profile '{}' in configuration file {}.", name, path));
+        }
+
+        profiles.push(config);
+    }
+
+    Ok(profiles)
+}
+
+// -----------------------------------------------------------------
+// profile_to_configuration - Maps a profile definition onto a
+// configuration, using the same sentinel values as get_options
+// -----------------------------------------------------------------
+fn profile_to_configuration(name: &str, definition: ProfileDefinition) -> Result<ProcDumpConfiguration, String>
+{
+    let mut config = ProcDumpConfiguration
+    {
+        profile_name: name.to_string(),
+        ..Default::default()
+    };
+
+    //
+    // Process target
+    //
+    let targets = definition.pid.is_some() as u32 + definition.pgid.is_some() as u32 + definition.process_name.is_some() as u32;
+    if targets != 1
+    {
+        return Err("exactly one of pid, pgid or process_name must be specified.".to_string());
+    }
+
+    if let Some(pid) = definition.pid
+    {
+        config.process_id = pid;
+    }
+    else if let Some(pgid) = definition.pgid
+    {
+        config.is_process_group_set = true;
+        config.process_pgid = pgid;
+    }
+    else if let Some(process_name) = definition.process_name
+    {
+        config.process_name = process_name;
+    }
+
+    if definition.wait && config.process_name.is_empty()
+    {
+        return Err("wait can only be used with process_name.".to_string());
+    }
+    config.waiting_process_name = definition.wait;
+
+    //
+    // Triggers
+    //
+    if let Some(cpu) = definition.cpu
+    {
+        config.trigger_threshold_cpu = cpu;
+        config.trigger_threshold_cpu_below = definition.cpu_below;
+    }
+
+    if let Some(memory) = definition.memory
+    {
+        config.trigger_threshold_mem = memory;
+        config.trigger_threshold_mem_below = definition.memory_below;
+    }
+
+    config.trigger_threshold_threads = definition.threads.unwrap_or(u32::MAX);
+    config.trigger_threshold_file_descriptors = definition.file_descriptors.unwrap_or(u32::MAX);
+    config.trigger_signal = definition.signal.unwrap_or(u32::MAX);
+
+    if definition.polling_frequency == Some(0)
+    {
+        return Err("polling_frequency must be greater than 0.".to_string());
+    }
+
+    config.polling_frequency = definition.polling_frequency.unwrap_or(u64::MAX);
+    config.threshold_seconds = definition.threshold_seconds.unwrap_or(u32::MAX);
+    config.number_of_dumps_to_collect = definition.number_of_dumps.unwrap_or(u32::MAX);
+
+    //
+    // Output
+    //
+    if let Some(output_directory) = definition.output_directory
+    {
+        match fs::metadata(&output_directory)
+        {
+            Ok(file) if file.is_dir() => config.core_dump_path = output_directory,
+            _ => return Err(format!("invalid directory ({}) provided for core dump output.", output_directory)),
+        }
+    }
+
+    config.core_dump_name = definition.dump_name.unwrap_or_default();
+    config.overwrite_existing_dump = definition.overwrite;
+    config.sample_file_enabled = definition.record_samples;
+    config.redacted_environment = definition.redact_environment;
+    config.history_seconds = definition.history_seconds;
+    config.min_free_space = definition.min_free_space;
+    config.dump_timeout = match definition.dump_timeout
+    {
+        Some(0) => return Err("dump_timeout must be greater than 0.".to_string()),
+        Some(seconds) => seconds,
+        None => u64::MAX,
+    };
+    config.retention_max_dumps = match definition.max_dumps
+    {
+        Some(0) => return Err("max_dumps must be greater than 0.".to_string()),
+        Some(count) => count,
+        None => u32::MAX,
+    };
+    config.retention_max_size = definition.max_total_size.unwrap_or(u64::MAX);
+    config.retention_max_age = definition.max_age_hours.unwrap_or(u64::MAX);
+
+    //
+    // Hooks
+    //
+    config.pre_dump_command = definition.pre_command.unwrap_or_default();
+    config.pre_dump_timeout = match definition.pre_command_timeout
+    {
+        Some(0) => return Err("pre_command_timeout must be greater than 0.".to_string()),
+        Some(seconds) => seconds,
+        None => u64::MAX,
+    };
+    config.post_dump_command = definition.post_command.unwrap_or_default();
+    config.post_dump_timeout = match definition.post_command_timeout
+    {
+        Some(0) => return Err("post_command_timeout must be greater than 0.".to_string()),
+        Some(seconds) => seconds,
+        None => u64::MAX,
+    };
+    config.post_dump_wait = definition.post_command_wait;
+    if let Some(compression) = definition.compression
+    {
+        match DumpCompression::parse(&compression)
+        {
+            Some(compression) => config.dump_compression = compression,
+            None => return Err(format!("invalid compression ({}), expected zstd or gzip.", compression)),
+        }
+    }
+    config.sample_file_max_size = match definition.sample_file_size
+    {
+        Some(0) => return Err("sample_file_size must be greater than 0.".to_string()),
+        Some(size) => size,
+        None => u64::MAX,
+    };
+
+    Ok(config)
+}
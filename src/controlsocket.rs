@@ -0,0 +1,313 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Unix domain control socket for runtime management.
+//
+// Line based protocol, one command per line. Every response ends with
+// a line containing either "OK" or "ERROR: <reason>".
+//
+//   list                    monitored processes and their latest samples
+//   dumps                   dump counts of monitored processes
+//   add <pid> [profile]     start monitoring a process
+//   remove <pid>            stop monitoring a process
+//   pause <pid>             keep sampling but don't trigger dumps
+//   resume <pid>            resume triggering dumps
+//   dump <pid>              write a manual dump now
+//
+//--------------------------------------------------------------------
+use crate::events::{Event, emit};
+use crate::logging::{Severity, log};
+use crate::monitor::*;
+use crate::processhelpers::is_process_running;
+use crate::triggerthreadprocs::manual_dump;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread;
+
+// --------------------------------------------------------------------
+// start_control_socket - Listens for control connections on the
+// specified socket path. Returns false if the socket can't be created.
+// --------------------------------------------------------------------
+pub fn start_control_socket(socket_path: &String, profile_monitors: ProfileMonitorList) -> bool
+{
+    // Remove a stale socket left behind by a previous instance
+    if let Ok(metadata) = fs::symlink_metadata(socket_path)
+    {
+        if !metadata.file_type().is_socket() || UnixStream::connect(socket_path).is_ok()
+        {
+            println!("Control socket {} is already in use.", socket_path);
+            return false;
+        }
+
+        let _ = fs::remove_file(socket_path);
+    }
+
+    let listener = match UnixListener::bind(socket_path)
+    {
+        Ok(listener) => listener,
+        Err(e) =>
+        {
+            println!("Failed to create control socket {}: {}", socket_path, e);
+            log(Severity::Error, &format!("Failed to create control socket {}: {}", socket_path, e));
+            return false;
+        },
+    };
+
+    // Only the owner may manage procdump
+    let _ = fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600));
+
+    let thread = thread::Builder::new().name("Control socket thread".to_string()).spawn(move ||
+    {
+        for stream in listener.incoming()
+        {
+            let stream = match stream
+            {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+
+            let profile_monitors = profile_monitors.clone();
+            let _ = thread::Builder::new().name("Control connection thread".to_string()).spawn(move || handle_connection(stream, &profile_monitors));
+        }
+    });
+
+    thread.is_ok()
+}
+
+// --------------------------------------------------------------------
+// stop_control_socket - Removes the control socket
+// --------------------------------------------------------------------
+pub fn stop_control_socket(socket_path: &String)
+{
+    let _ = fs::remove_file(socket_path);
+}
+
+// --------------------------------------------------------------------
+// handle_connection - Executes commands of a client until it disconnects
+// --------------------------------------------------------------------
+fn handle_connection(stream: UnixStream, profile_monitors: &ProfileMonitorList)
+{
+    let mut writer = match stream.try_clone()
+    {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines()
+    {
+        let line = match line
+        {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        if line.trim().is_empty()
+        {
+            continue;
+        }
+
+        log(Severity::Info, &format!("Control command: {}", line.trim()));
+        let response = match execute_command(line.trim(), profile_monitors)
+        {
+            Ok(output) => format!("{}OK\n", output),
+            Err(e) =>
+            {
+                log(Severity::Warning, &format!("Control command '{}' failed: {}", line.trim(), e));
+                format!("ERROR: {}\n", e)
+            },
+        };
+
+        if writer.write_all(response.as_bytes()).is_err()
+        {
+            break;
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// execute_command - Executes a single command and returns its output
+// --------------------------------------------------------------------
+fn execute_command(command: &str, profile_monitors: &ProfileMonitorList) -> Result<String, String>
+{
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args[0]
+    {
+        "list" => Ok(list_processes(profile_monitors, false)),
+        "dumps" => Ok(list_processes(profile_monitors, true)),
+        "add" => add_process(&args, profile_monitors),
+        "remove" => remove_process(parse_pid(&args)?, profile_monitors),
+        "pause" => set_paused(parse_pid(&args)?, profile_monitors, true),
+        "resume" => set_paused(parse_pid(&args)?, profile_monitors, false),
+        "dump" => dump_process(parse_pid(&args)?, profile_monitors),
+        _ => Err(format!("Unknown command '{}'", args[0])),
+    }
+}
+
+// --------------------------------------------------------------------
+// parse_pid - Parses the pid argument of a command
+// --------------------------------------------------------------------
+fn parse_pid(args: &[&str]) -> Result<i32, String>
+{
+    match args.get(1)
+    {
+        Some(pid) => pid.parse::<i32>().map_err(|_| format!("Invalid pid '{}'", pid)),
+        None => Err(format!("Usage: {} <pid>", args[0])),
+    }
+}
+
+// --------------------------------------------------------------------
+// with_monitored_process - Runs the action on the map entry of the given
+// pid while holding the lock of the process map the entry was found in
+// --------------------------------------------------------------------
+fn with_monitored_process<T>(pid: i32, profile_monitors: &ProfileMonitorList, action: impl FnOnce(&mut MonitoredProcessMapEntry) -> T) -> Result<T, String>
+{
+    let profile_monitors = profile_monitors.lock().unwrap().clone();
+    for profile_monitor in profile_monitors
+    {
+        let mut monitored_process_map = profile_monitor.monitored_process_map.lock().unwrap();
+        if let Some(entry) = monitored_process_map.get_mut(&pid)
+        {
+            return Ok(action(entry));
+        }
+    }
+
+    Err(format!("Process {} is not monitored", pid))
+}
+
+// --------------------------------------------------------------------
+// list_processes - Lists monitored processes with their latest samples
+// or their dump counts
+// --------------------------------------------------------------------
+fn list_processes(profile_monitors: &ProfileMonitorList, dump_counts: bool) -> String
+{
+    let mut output = String::new();
+    let profile_monitors = profile_monitors.lock().unwrap().clone();
+    for profile_monitor in profile_monitors
+    {
+        let profile_name = profile_monitor.config.lock().unwrap().profile_name.clone();
+        let monitored_process_map = profile_monitor.monitored_process_map.lock().unwrap();
+
+        let mut pids: Vec<&i32> = monitored_process_map.keys().collect();
+        pids.sort();
+        for pid in pids
+        {
+            let entry = &monitored_process_map[pid];
+            let lock = entry.config.lock().unwrap();
+
+            output.push_str(&format!("{} {}", pid, lock.process_name));
+            if !profile_name.is_empty()
+            {
+                output.push_str(&format!(" profile={}", profile_name));
+            }
+
+            if dump_counts
+            {
+                output.push_str(&format!(" dumps={}/{}\n", lock.number_of_dumps_collected, lock.number_of_dumps_to_collect));
+                continue;
+            }
+
+            let state = if entry.threads.is_empty() || is_monitor_finished(entry) { "stopped" } else if lock.paused { "paused" } else { "active" };
+            let sample = &lock.last_sample;
+            output.push_str(&format!(" state={} cpu={}% memory={}MB threads={} fds={}\n",
+                state,
+                sample.cpu_usage.map_or("n/a".to_string(), |v| v.to_string()),
+                sample.memory_usage.map_or("n/a".to_string(), |v| v.to_string()),
+                sample.thread_count.map_or("n/a".to_string(), |v| v.to_string()),
+                sample.file_descriptor_count.map_or("n/a".to_string(), |v| v.to_string())));
+        }
+    }
+
+    output
+}
+
+// --------------------------------------------------------------------
+// add_process - Starts monitoring a process using the settings of a
+// profile (which can be omitted if there's only one)
+// --------------------------------------------------------------------
+fn add_process(args: &[&str], profile_monitors: &ProfileMonitorList) -> Result<String, String>
+{
+    let pid = parse_pid(args)?;
+    if !is_process_running(pid)
+    {
+        return Err(format!("No process matching the specified PID ({}) can be found", pid));
+    }
+
+    let profile_monitor =
+    {
+        let profile_monitors = profile_monitors.lock().unwrap();
+        match args.get(2)
+        {
+            Some(name) => profile_monitors.iter().find(|p| p.config.lock().unwrap().profile_name == *name).cloned(),
+            None if profile_monitors.len() == 1 => profile_monitors.first().cloned(),
+            None => return Err("Usage: add <pid> <profile>".to_string()),
+        }
+    };
+
+    let profile_monitor = match profile_monitor
+    {
+        Some(profile_monitor) => profile_monitor,
+        None => return Err(format!("Unknown profile '{}'", args[2])),
+    };
+
+    let mut monitored_process_map = profile_monitor.monitored_process_map.lock().unwrap();
+    if let Some(entry) = monitored_process_map.get(&pid)
+    {
+        if !is_monitor_finished(entry)
+        {
+            return Err(format!("Process {} is already monitored", pid));
+        }
+    }
+
+    let mut config = profile_monitor.config.lock().unwrap().clone();
+    let mut entry = get_new_process_map_entry(&mut config, pid);
+    if !start_monitor(&mut entry)
+    {
+        return Err(format!("Failed to start monitor for pid: {}", pid));
+    }
+
+    monitored_process_map.insert(pid, entry);
+
+    Ok(String::new())
+}
+
+// --------------------------------------------------------------------
+// remove_process - Stops monitoring a process. The entry is kept so
+// the process isn't picked up again by the profile.
+// --------------------------------------------------------------------
+fn remove_process(pid: i32, profile_monitors: &ProfileMonitorList) -> Result<String, String>
+{
+    with_monitored_process(pid, profile_monitors, |entry|
+    {
+        stop_monitor(entry);
+        entry.active = false;
+        emit(Event::MonitorStopped { pid, process_name: &entry.config.lock().unwrap().process_name });
+    })?;
+
+    Ok(String::new())
+}
+
+// --------------------------------------------------------------------
+// set_paused - Pauses or resumes the monitors of a process
+// --------------------------------------------------------------------
+fn set_paused(pid: i32, profile_monitors: &ProfileMonitorList, paused: bool) -> Result<String, String>
+{
+    with_monitored_process(pid, profile_monitors, |entry| entry.config.lock().unwrap().paused = paused)?;
+
+    Ok(String::new())
+}
+
+// --------------------------------------------------------------------
+// dump_process - Writes a manual dump of a monitored process. The map
+// isn't kept locked while the dump is written.
+// --------------------------------------------------------------------
+fn dump_process(pid: i32, profile_monitors: &ProfileMonitorList) -> Result<String, String>
+{
+    let config = with_monitored_process(pid, profile_monitors, |entry| entry.config.clone())?;
+    manual_dump(&config)?;
+
+    Ok(String::new())
+}
@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Daemon mode - long running monitoring driven by a configuration file
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::configfile::load_profiles;
+use crate::controlsocket::{start_control_socket, stop_control_socket};
+use crate::dumpworker::wait_for_queued_dumps;
+use crate::dumpwriter::remove_partial_dumps;
+use crate::logging::{Severity, log};
+use crate::monitor::*;
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use crate::systemdnotify::{notify, watchdog_interval};
+use nix::sys::signal::Signal;
+use std::sync::Arc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// Wake up interval of the daemon loop when the watchdog is disabled
+const DAEMON_IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+// --------------------------------------------------------------------
+// run_daemon - Monitors the profiles of the configuration file until
+// SIGTERM/SIGINT, reloading the configuration on SIGHUP. The signals are
+// received from the signal handler thread.
+// --------------------------------------------------------------------
+pub fn run_daemon(config: &ProcDumpConfiguration, profile_monitors: &ProfileMonitorList, signals: Receiver<Signal>)
+{
+    let config_file_path = &config.config_file_path;
+
+    let profiles = match load_profiles(config_file_path)
+    {
+        Ok(profiles) => profiles,
+        Err(e) => { println!("{}", e); return; },
+    };
+
+    remove_partial_dumps(&profiles);
+
+    if !config.control_socket_path.is_empty() && !start_control_socket(&config.control_socket_path, profile_monitors.clone())
+    {
+        return;
+    }
+
+    for profile in profiles
+    {
+        if let Some(profile_monitor) = start_profile_monitor(profile)
+        {
+            profile_monitors.lock().unwrap().push(profile_monitor);
+        }
+    }
+
+    notify(&format!("READY=1\nSTATUS=Monitoring {} profile(s)", profile_monitors.lock().unwrap().len()));
+
+    let watchdog = watchdog_interval();
+    let mut last_watchdog = Instant::now();
+    loop
+    {
+        let timeout = match watchdog
+        {
+            Some(interval) => interval.saturating_sub(last_watchdog.elapsed()),
+            None => DAEMON_IDLE_INTERVAL,
+        };
+
+        match signals.recv_timeout(timeout)
+        {
+            Ok(Signal::SIGHUP) =>
+            {
+                notify("RELOADING=1");
+                println!("Reloading configuration from {}", config_file_path);
+                log(Severity::Info, &format!("Reloading configuration from {}", config_file_path));
+                reload_profiles(config_file_path, profile_monitors);
+                notify(&format!("READY=1\nSTATUS=Monitoring {} profile(s)", profile_monitors.lock().unwrap().len()));
+            },
+            Ok(_) | Err(RecvTimeoutError::Disconnected) =>
+            {
+                break;
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+        }
+
+        if let Some(interval) = watchdog
+        {
+            if last_watchdog.elapsed() >= interval
+            {
+                notify("WATCHDOG=1");
+                last_watchdog = Instant::now();
+            }
+        }
+    }
+
+    notify("STOPPING=1");
+    println!("Stopping all monitors");
+    for profile_monitor in profile_monitors.lock().unwrap().iter()
+    {
+        stop_profile_monitor(profile_monitor);
+    }
+
+    wait_for_queued_dumps();
+
+    if !config.control_socket_path.is_empty()
+    {
+        stop_control_socket(&config.control_socket_path);
+    }
+}
+
+// --------------------------------------------------------------------
+// reload_profiles - Reloads the configuration file and adds, removes,
+// retunes or restarts profiles. If the file is invalid the current
+// profiles are kept. The changes are worked out under the profile list
+// lock, monitors are stopped and started without holding it.
+// --------------------------------------------------------------------
+fn reload_profiles(config_file_path: &String, profile_monitors: &ProfileMonitorList)
+{
+    let profiles = match load_profiles(config_file_path)
+    {
+        Ok(profiles) => profiles,
+        Err(e) =>
+        {
+            println!("{}", e);
+            println!("Configuration not reloaded, keeping current profiles.");
+            log(Severity::Warning, &format!("Configuration not reloaded: {}", e));
+            return;
+        },
+    };
+
+    let mut removed = Vec::new();
+    let mut retuned = Vec::new();
+    let mut restarted = Vec::new();
+    let mut added = Vec::new();
+    {
+        let mut profile_monitors = profile_monitors.lock().unwrap();
+
+        // Remove profiles that are gone or now target something else
+        profile_monitors.retain(|profile_monitor|
+        {
+            let current = profile_monitor.config.lock().unwrap().clone();
+            let keep = profiles.iter().any(|profile| profile.profile_name == current.profile_name && is_same_target(profile, &current));
+            if !keep
+            {
+                removed.push(profile_monitor.clone());
+            }
+
+            keep
+        });
+
+        for profile in profiles
+        {
+            let existing = profile_monitors.iter().find(|profile_monitor| profile_monitor.config.lock().unwrap().profile_name == profile.profile_name);
+            match existing
+            {
+                // Profiles that haven't changed are left alone, even if they have run to completion
+                Some(profile_monitor) if *profile_monitor.config.lock().unwrap() == profile => {},
+                Some(profile_monitor) if is_profile_monitor_running(profile_monitor) => retuned.push((profile_monitor.clone(), profile)),
+                Some(profile_monitor) => restarted.push((profile_monitor.clone(), profile)),
+                None => added.push(profile),
+            }
+        }
+    }
+
+    for profile_monitor in removed
+    {
+        println!("Removing profile {}", profile_monitor.config.lock().unwrap().profile_name);
+        stop_profile_monitor(&profile_monitor);
+    }
+
+    for (profile_monitor, profile) in retuned
+    {
+        println!("Retuning profile {}", profile.profile_name);
+        retune_profile_monitor(&profile_monitor, &profile);
+    }
+
+    for (profile_monitor, profile) in restarted
+    {
+        // The profile has run to completion, start it over with the new settings
+        println!("Restarting profile {}", profile.profile_name);
+        let restarted_monitor = restart_profile_monitor(&profile_monitor, profile);
+
+        let mut profile_monitors = profile_monitors.lock().unwrap();
+        let index = profile_monitors.iter().position(|p| Arc::ptr_eq(p, &profile_monitor));
+        match (index, restarted_monitor)
+        {
+            (Some(index), Some(restarted_monitor)) => profile_monitors[index] = restarted_monitor,
+            (Some(index), None) => { profile_monitors.remove(index); },
+            (None, Some(restarted_monitor)) => profile_monitors.push(restarted_monitor),
+            (None, None) => {},
+        }
+    }
+
+    for profile in added
+    {
+        println!("Adding profile {}", profile.profile_name);
+        if let Some(profile_monitor) = start_profile_monitor(profile)
+        {
+            profile_monitors.lock().unwrap().push(profile_monitor);
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// is_same_target - returns true if both profiles select the same
+// process(es), false otherwise
+// --------------------------------------------------------------------
+fn is_same_target(a: &ProcDumpConfiguration, b: &ProcDumpConfiguration) -> bool
+{
+    a.process_id == b.process_id &&
+        a.process_pgid == b.process_pgid &&
+        a.is_process_group_set == b.is_process_group_set &&
+        a.process_name == b.process_name &&
+        a.waiting_process_name == b.waiting_process_name
+}
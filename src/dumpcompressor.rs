@@ -0,0 +1,107 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Compression of dumps (-z). gdb seeks while it writes a core file so
+// gcore can't write into a pipe, it writes an uncompressed partial dump
+// which is then compressed and removed.
+//
+//--------------------------------------------------------------------
+use crate::dumpworker::is_dump_cancelled;
+use crate::procdumpconfiguration::DumpCompression;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+//
+// Result of a compressed dump
+//
+#[derive(Serialize)]
+pub struct CompressionStats
+{
+    pub algorithm: DumpCompression,
+    pub uncompressed_size: u64,
+    pub compressed_size: u64,
+    pub ratio: f64,
+}
+
+// --------------------------------------------------------------------
+// get_compressed_extension - returns the file extension of a dump
+// compressed with the specified algorithm
+// --------------------------------------------------------------------
+pub fn get_compressed_extension(compression: DumpCompression) -> &'static str
+{
+    match compression
+    {
+        DumpCompression::None => "",
+        DumpCompression::Zstd => ".zst",
+        DumpCompression::Gzip => ".gz",
+    }
+}
+
+// --------------------------------------------------------------------
+// compress_dump - Compresses the dump at input_path into output_path
+// --------------------------------------------------------------------
+pub fn compress_dump(input_path: &str, output_path: &str, compression: DumpCompression) -> io::Result<CompressionStats>
+{
+    let input = File::open(input_path)?;
+    let output = File::create(output_path)?;
+    let uncompressed_size = match compression
+    {
+        DumpCompression::Zstd =>
+        {
+            let mut encoder = zstd::Encoder::new(output, ZSTD_LEVEL)?;
+            let size = copy(input, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+            size
+        },
+        _ =>
+        {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            let size = copy(input, &mut encoder)?;
+            encoder.finish()?.sync_all()?;
+            size
+        },
+    };
+
+    let compressed_size = fs::metadata(output_path)?.len();
+    Ok(CompressionStats
+    {
+        algorithm: compression,
+        uncompressed_size,
+        compressed_size,
+        ratio: if compressed_size > 0 { uncompressed_size as f64 / compressed_size as f64 } else { 0.0 },
+    })
+}
+
+// --------------------------------------------------------------------
+// copy - Copies the input to the encoder and returns the byte count,
+// stops if dumps are cancelled
+// --------------------------------------------------------------------
+fn copy(mut input: File, encoder: &mut impl Write) -> io::Result<u64>
+{
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut total = 0u64;
+    loop
+    {
+        if is_dump_cancelled()
+        {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "dump was cancelled"));
+        }
+
+        let read = match input.read(&mut buffer)
+        {
+            Ok(0) => return Ok(total),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        encoder.write_all(&buffer[..read])?;
+        total += read as u64;
+    }
+}
@@ -0,0 +1,479 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Per directory manifest of the dumps written by procdump with their
+// SHA-256, the retention policy applied to them (-maxdumps, -maxsize,
+// -maxage) and their verification (procdump verify). Only dumps listed
+// in the manifest are ever deleted.
+//
+//--------------------------------------------------------------------
+use crate::dumpworker::is_dump_cancelled;
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use chrono::Local;
+use nix::fcntl::{FlockArg, flock};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+const MANIFEST_FILE_NAME: &str = ".procdump_manifest.jsonl";
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+//
+// A dump listed in the manifest
+//
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry
+{
+    pub dump_path: String,
+    pub size: u64,
+    #[serde(default)]
+    pub sha256: String,             // empty for dumps recorded without checksum
+    pub created: i64,               // seconds since the epoch
+    pub pid: i32,
+    pub process_name: String,
+    pub trigger: String,
+}
+
+// --------------------------------------------------------------------
+// get_manifest_path - returns the manifest of a dump folder
+// --------------------------------------------------------------------
+pub fn get_manifest_path(directory: &str) -> String
+{
+    format!("{}/{}", directory, MANIFEST_FILE_NAME)
+}
+
+// --------------------------------------------------------------------
+// lock_manifest - Opens the manifest of a dump folder, creating it if
+// needed, and locks it against the other monitors and procdump
+// instances sharing the folder. The lock is held until the file is
+// closed.
+// --------------------------------------------------------------------
+fn lock_manifest(directory: &str) -> io::Result<File>
+{
+    let manifest_path = get_manifest_path(directory);
+    loop
+    {
+        let file = OpenOptions::new().create(true).append(true).open(&manifest_path)?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+
+        // The manifest may have been replaced while waiting for the lock
+        let locked = file.metadata()?;
+        match fs::metadata(&manifest_path)
+        {
+            Ok(current) if current.ino() == locked.ino() && current.dev() == locked.dev() => return Ok(file),
+            _ => continue,
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// read_manifest - returns the entries of the manifest of a dump folder.
+// A dump recorded again (overwritten) keeps its latest entry.
+// --------------------------------------------------------------------
+pub fn read_manifest(directory: &str) -> Vec<ManifestEntry>
+{
+    let contents = match fs::read_to_string(get_manifest_path(directory))
+    {
+        Ok(contents) => contents,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut entries: Vec<ManifestEntry> = Vec::new();
+    for entry in contents.lines().filter_map(|line| serde_json::from_str::<ManifestEntry>(line).ok())
+    {
+        entries.retain(|existing| existing.dump_path != entry.dump_path);
+        entries.push(entry);
+    }
+
+    entries
+}
+
+// --------------------------------------------------------------------
+// write_manifest - Replaces the manifest of a dump folder. The manifest
+// must be locked.
+// --------------------------------------------------------------------
+fn write_manifest(directory: &str, entries: &[ManifestEntry]) -> bool
+{
+    let mut contents = String::new();
+    for entry in entries
+    {
+        if let Ok(line) = serde_json::to_string(entry)
+        {
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+    }
+
+    // Replace atomically so a crash never leaves a truncated manifest
+    let manifest_path = get_manifest_path(directory);
+    let temporary_path = format!("{}.tmp", manifest_path);
+    fs::write(&temporary_path, contents).is_ok() && fs::rename(&temporary_path, &manifest_path).is_ok()
+}
+
+// --------------------------------------------------------------------
+// record_dump - Appends a new dump to the manifest of its folder and
+// applies the retention policy
+// --------------------------------------------------------------------
+pub fn record_dump(config: &ProcDumpConfiguration, dump_path: &str, trigger_type: &str)
+{
+    // Hash before taking the lock, dumps can be large
+    let sha256 = match hash_file(dump_path)
+    {
+        Ok(sha256) => sha256,
+        Err(e) =>
+        {
+            log(Severity::Warning, &format!("Failed to compute SHA-256 of {}: {}", dump_path, e));
+            String::new()
+        },
+    };
+
+    let entry = ManifestEntry
+    {
+        dump_path: dump_path.to_string(),
+        size: fs::metadata(dump_path).map(|metadata| metadata.len()).unwrap_or(0),
+        sha256,
+        created: Local::now().timestamp(),
+        pid: config.process_id,
+        process_name: config.process_name.clone(),
+        trigger: trigger_type.to_string(),
+    };
+
+    // A single write, an overwritten dump's entry replaces its previous one when read
+    let appended = serde_json::to_string(&entry).map_err(io::Error::from).and_then(|line|
+    {
+        let mut manifest = lock_manifest(&config.core_dump_path)?;
+        manifest.write_all(format!("{}\n", line).as_bytes())
+    });
+
+    if let Err(e) = appended
+    {
+        log(Severity::Warning, &format!("Failed to add {} to manifest {}: {}", dump_path, get_manifest_path(&config.core_dump_path), e));
+    }
+
+    apply_retention(config, dump_path);
+}
+
+// --------------------------------------------------------------------
+// apply_retention - Deletes the oldest dumps of the folder until the
+// maximum number of dumps, total size and age are respected. The dump
+// just recorded is never deleted. The manifest is only rewritten if
+// dumps were deleted or removed by someone else.
+// --------------------------------------------------------------------
+fn apply_retention(config: &ProcDumpConfiguration, recorded_dump_path: &str)
+{
+    if config.retention_max_dumps == u32::MAX && config.retention_max_size == u64::MAX && config.retention_max_age == u64::MAX
+    {
+        return;
+    }
+
+    let directory = &config.core_dump_path;
+    let _manifest = match lock_manifest(directory)
+    {
+        Ok(manifest) => manifest,
+        Err(e) =>
+        {
+            log(Severity::Warning, &format!("Failed to lock manifest {}: {}", get_manifest_path(directory), e));
+            return;
+        },
+    };
+
+    // Forget dumps that were removed by someone else
+    let listed = read_manifest(directory);
+    let listed_count = listed.len();
+    let (recorded, mut entries): (Vec<ManifestEntry>, Vec<ManifestEntry>) = listed.into_iter()
+        .filter(|entry| Path::new(&entry.dump_path).exists())
+        .partition(|entry| entry.dump_path == recorded_dump_path);
+    entries.sort_by_key(|entry| entry.created);
+
+    let now = Local::now().timestamp();
+    let max_size = config.retention_max_size.saturating_mul(1024 * 1024);
+    let max_age = config.retention_max_age.saturating_mul(3600);
+    let mut total_size: u64 = entries.iter().chain(recorded.iter()).map(|entry| entry.size).sum();
+
+    while let Some(oldest) = entries.first()
+    {
+        let reason = if (entries.len() + recorded.len()) as u64 > config.retention_max_dumps as u64
+        {
+            format!("more than {} dumps", config.retention_max_dumps)
+        }
+        else if total_size > max_size
+        {
+            format!("dumps exceed {}MB", config.retention_max_size)
+        }
+        else if (now - oldest.created).max(0) as u64 > max_age
+        {
+            format!("older than {} hours", config.retention_max_age)
+        }
+        else
+        {
+            break;
+        };
+
+        let oldest = entries.remove(0);
+        total_size -= oldest.size;
+        delete_dump(&oldest.dump_path, &reason);
+    }
+
+    if !recorded.is_empty() && total_size > max_size
+    {
+        println!("Dump {} alone exceeds the {}MB retention limit and was kept", recorded_dump_path, config.retention_max_size);
+        log(Severity::Warning, &format!("Dump {} alone exceeds the {}MB retention limit and was kept", recorded_dump_path, config.retention_max_size));
+    }
+
+    entries.extend(recorded);
+    if entries.len() != listed_count && !write_manifest(directory, &entries)
+    {
+        log(Severity::Warning, &format!("Failed to update manifest {}", get_manifest_path(directory)));
+    }
+}
+
+// --------------------------------------------------------------------
+// delete_dump - Deletes a dump and its metadata file
+// --------------------------------------------------------------------
+fn delete_dump(dump_path: &str, reason: &str)
+{
+    match fs::remove_file(dump_path)
+    {
+        Ok(_) =>
+        {
+            println!("Deleted dump {} (retention: {})", dump_path, reason);
+            log(Severity::Info, &format!("Deleted dump {} (retention: {})", dump_path, reason));
+        },
+        Err(e) =>
+        {
+            println!("Failed to delete dump {}: {}", dump_path, e);
+            log(Severity::Warning, &format!("Failed to delete dump {}: {}", dump_path, e));
+        },
+    }
+
+    let _ = fs::remove_file(format!("{}.json", dump_path));
+}
+
+// --------------------------------------------------------------------
+// hash_file - returns the SHA-256 of a file as a hex string. Gives up
+// if dumps are cancelled.
+// --------------------------------------------------------------------
+fn hash_file(path: &str) -> io::Result<String>
+{
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    loop
+    {
+        if is_dump_cancelled()
+        {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "dumps were cancelled"));
+        }
+
+        let read = match file.read(&mut buffer)
+        {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// --------------------------------------------------------------------
+// verify_manifest - Re-hashes the dumps listed in the manifest of a dump
+// folder and reports the missing and corrupted ones. Dumps are looked
+// up in the folder so it can be verified after being copied elsewhere.
+// Returns false if any dump is missing or corrupted.
+// --------------------------------------------------------------------
+pub fn verify_manifest(directory: &str) -> bool
+{
+    let manifest_path = get_manifest_path(directory);
+    if !Path::new(&manifest_path).exists()
+    {
+        println!("No manifest found in {}", directory);
+        return false;
+    }
+
+    let entries = read_manifest(directory);
+    let mut missing = 0;
+    let mut corrupted = 0;
+    for entry in &entries
+    {
+        let file_name = Path::new(&entry.dump_path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let dump_path = format!("{}/{}", directory, file_name);
+
+        let size = match fs::metadata(&dump_path)
+        {
+            Ok(metadata) => metadata.len(),
+            Err(_) =>
+            {
+                println!("MISSING    {}", dump_path);
+                missing += 1;
+                continue;
+            },
+        };
+
+        if size != entry.size
+        {
+            println!("CORRUPTED  {} (size {} bytes, expected {})", dump_path, size, entry.size);
+            corrupted += 1;
+        }
+        else if entry.sha256.is_empty()
+        {
+            println!("UNVERIFIED {} (no checksum recorded)", dump_path);
+        }
+        else
+        {
+            match hash_file(&dump_path)
+            {
+                Ok(sha256) if sha256 == entry.sha256 => println!("OK         {}", dump_path),
+                Ok(sha256) =>
+                {
+                    println!("CORRUPTED  {} (SHA-256 {}, expected {})", dump_path, sha256, entry.sha256);
+                    corrupted += 1;
+                },
+                Err(e) =>
+                {
+                    println!("CORRUPTED  {} ({})", dump_path, e);
+                    corrupted += 1;
+                },
+            }
+        }
+    }
+
+    println!();
+    println!("{} dump(s) in manifest, {} missing, {} corrupted", entries.len(), missing, corrupted);
+
+    missing == 0 && corrupted == 0
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Creates an empty dump folder for a test
+    fn dump_folder(test_name: &str) -> String
+    {
+        let directory = std::env::temp_dir().join(format!("procdump_manifest_test_{}_{}", std::process::id(), test_name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory.to_string_lossy().to_string()
+    }
+
+    // Writes a dump of the given size and returns its manifest entry
+    fn dump(directory: &str, name: &str, size: usize, created: i64) -> ManifestEntry
+    {
+        let dump_path = format!("{}/{}", directory, name);
+        fs::write(&dump_path, vec![0u8; size]).unwrap();
+        ManifestEntry { dump_path, size: size as u64, sha256: String::new(), created, pid: 1234, process_name: "app".to_string(), trigger: "cpu".to_string() }
+    }
+
+    fn manifest_dumps(directory: &str) -> Vec<String>
+    {
+        read_manifest(directory).into_iter().map(|entry| entry.dump_path).collect()
+    }
+
+    #[test]
+    fn appends_recorded_dumps()
+    {
+        let directory = dump_folder("append");
+        let first = dump(&directory, "a", 10, 0);
+        let second = dump(&directory, "b", 10, 0);
+
+        let config = ProcDumpConfiguration { core_dump_path: directory.clone(), process_id: 1234, ..Default::default() };
+        record_dump(&config, &first.dump_path, "cpu");
+        record_dump(&config, &second.dump_path, "memory");
+
+        // An overwritten dump is appended again and replaces its previous entry
+        fs::write(&first.dump_path, vec![0u8; 20]).unwrap();
+        record_dump(&config, &first.dump_path, "threads");
+
+        let contents = fs::read_to_string(get_manifest_path(&directory)).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+
+        let entries = read_manifest(&directory);
+        assert_eq!(entries.iter().map(|entry| entry.dump_path.clone()).collect::<Vec<String>>(), vec![second.dump_path, first.dump_path]);
+        assert_eq!((entries[1].size, entries[1].trigger.as_str()), (20, "threads"));
+        assert_eq!(entries[1].sha256.len(), 64);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn deletes_oldest_dumps_over_max_dumps()
+    {
+        let directory = dump_folder("max_dumps");
+        let now = Local::now().timestamp();
+        let entries = vec![dump(&directory, "a", 10, now - 30), dump(&directory, "b", 10, now - 20), dump(&directory, "c", 10, now - 10)];
+        let recorded = entries[2].dump_path.clone();
+        write_manifest(&directory, &entries);
+
+        let config = ProcDumpConfiguration { core_dump_path: directory.clone(), retention_max_dumps: 2, ..Default::default() };
+        apply_retention(&config, &recorded);
+
+        assert!(!Path::new(&entries[0].dump_path).exists());
+        assert_eq!(manifest_dumps(&directory), vec![entries[1].dump_path.clone(), recorded]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_recorded_dump_over_max_size()
+    {
+        let directory = dump_folder("max_size");
+        let now = Local::now().timestamp();
+        let entries = vec![dump(&directory, "a", 10, now - 20), dump(&directory, "b", 2 * 1024 * 1024, now - 10)];
+        let recorded = entries[1].dump_path.clone();
+        write_manifest(&directory, &entries);
+
+        let config = ProcDumpConfiguration { core_dump_path: directory.clone(), retention_max_size: 1, ..Default::default() };
+        apply_retention(&config, &recorded);
+
+        assert!(!Path::new(&entries[0].dump_path).exists());
+        assert!(Path::new(&recorded).exists());
+        assert_eq!(manifest_dumps(&directory), vec![recorded]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_recorded_dump_over_max_age_and_max_dumps()
+    {
+        // An overwritten dump is recorded again under its old creation time
+        let directory = dump_folder("max_age");
+        let now = Local::now().timestamp();
+        let entries = vec![dump(&directory, "a", 10, now - 3 * 3600), dump(&directory, "b", 10, now)];
+        let recorded = entries[0].dump_path.clone();
+        write_manifest(&directory, &entries);
+
+        let config = ProcDumpConfiguration { core_dump_path: directory.clone(), retention_max_dumps: 0, retention_max_age: 1, ..Default::default() };
+        apply_retention(&config, &recorded);
+
+        assert!(Path::new(&recorded).exists());
+        assert!(!Path::new(&entries[1].dump_path).exists());
+        assert_eq!(manifest_dumps(&directory), vec![recorded]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn forgets_dumps_removed_by_someone_else()
+    {
+        let directory = dump_folder("removed");
+        let now = Local::now().timestamp();
+        let entries = vec![dump(&directory, "a", 10, now - 20), dump(&directory, "b", 10, now - 10)];
+        let recorded = entries[1].dump_path.clone();
+        write_manifest(&directory, &entries);
+        fs::remove_file(&entries[0].dump_path).unwrap();
+
+        let config = ProcDumpConfiguration { core_dump_path: directory.clone(), retention_max_dumps: 5, ..Default::default() };
+        apply_retention(&config, &recorded);
+
+        assert_eq!(manifest_dumps(&directory), vec![recorded]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
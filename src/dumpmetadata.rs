@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Writes the <dump>.json metadata sidecar next to each dump so dumps
+// can be triaged without opening them
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::dumpcompressor::CompressionStats;
+use crate::dumpwriter::TriggerContext;
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use crate::processhelpers::{HistorySample, get_process_start_time, get_system_uptime};
+use chrono::{DateTime, Local, TimeZone};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+const REDACTED_VALUE: &str = "<redacted>";
+
+#[derive(Serialize)]
+struct DumpMetadata<'a>
+{
+    dump_path: &'a str,
+    dump_size: Option<u64>,
+    timestamp: String,
+    gcore_duration_ms: u128,
+    compression: Option<&'a CompressionStats>,
+    trigger: &'a TriggerContext,
+    pre_dump_output: Option<&'a str>,
+    process: ProcessMetadata,
+    host: HostMetadata,
+    history: &'a VecDeque<HistorySample>,
+    configuration: &'a ProcDumpConfiguration,
+}
+
+#[derive(Serialize)]
+struct ProcessMetadata
+{
+    pid: i32,
+    name: String,
+    cmdline: Vec<String>,
+    exe: Option<String>,
+    uid: Option<u32>,
+    cgroup: Option<String>,
+    start_time: Option<String>,
+    uptime_seconds: Option<f64>,
+    exited: bool,
+    environment: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct HostMetadata
+{
+    hostname: Option<String>,
+    kernel_version: Option<String>,
+    uptime_seconds: Option<f64>,
+}
+
+// --------------------------------------------------------------------
+// write_dump_metadata - Writes <dump_path>.json. Returns false if the
+// file can't be written. process_exited tells the process exited while
+// it was being dumped.
+// --------------------------------------------------------------------
+pub fn write_dump_metadata(config: &ProcDumpConfiguration, trigger: &TriggerContext, dump_path: &str, gcore_duration_ms: u128, compression: Option<&CompressionStats>, pre_dump_output: Option<&str>, process_exited: bool) -> bool
+{
+    let metadata = DumpMetadata
+    {
+        dump_path,
+        dump_size: fs::metadata(dump_path).map(|metadata| metadata.len()).ok(),
+        timestamp: Local::now().to_rfc3339(),
+        gcore_duration_ms,
+        compression,
+        trigger,
+        pre_dump_output,
+        process: get_process_metadata(config, process_exited),
+        host: HostMetadata
+        {
+            hostname: nix::unistd::gethostname().ok().and_then(|name| name.into_string().ok()),
+            kernel_version: fs::read_to_string("/proc/sys/kernel/osrelease").ok().map(|release| release.trim().to_string()),
+            uptime_seconds: get_system_uptime(),
+        },
+        history: &config.sample_history,
+        configuration: config,
+    };
+
+    let json = match serde_json::to_string_pretty(&metadata)
+    {
+        Ok(json) => json,
+        Err(_) => return false,
+    };
+
+    fs::write(format!("{}.json", dump_path), json).is_ok()
+}
+
+// --------------------------------------------------------------------
+// get_process_metadata - Collects the details of the dumped process
+// --------------------------------------------------------------------
+fn get_process_metadata(config: &ProcDumpConfiguration, exited: bool) -> ProcessMetadata
+{
+    let proc_path = format!("/proc/{}", config.process_id);
+
+    let cmdline = fs::read(format!("{}/cmdline", proc_path)).unwrap_or_default();
+    let environ = fs::read(format!("{}/environ", proc_path)).unwrap_or_default();
+
+    let mut environment = BTreeMap::new();
+    for variable in split_nul(&environ)
+    {
+        if let Some((name, value)) = variable.split_once('=')
+        {
+            let value = if is_redacted(name, &config.redacted_environment) { REDACTED_VALUE.to_string() } else { value.to_string() };
+            environment.insert(name.to_string(), value);
+        }
+    }
+
+    // Process start time is in clock ticks since boot
+    let hz = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK).ok().flatten().unwrap_or(100) as f64;
+    let process_start_time = get_process_start_time(config.process_id);
+    let process_uptime = get_system_uptime().filter(|_| process_start_time != u64::MAX).map(|uptime| uptime - process_start_time as f64 / hz);
+    let start_time = process_uptime.and_then(get_start_time).map(|start_time| start_time.to_rfc3339());
+
+    ProcessMetadata
+    {
+        pid: config.process_id,
+        name: config.process_name.clone(),
+        cmdline: split_nul(&cmdline),
+        exe: fs::read_link(format!("{}/exe", proc_path)).ok().map(|exe| exe.to_string_lossy().to_string()),
+        uid: fs::metadata(&proc_path).map(|metadata| metadata.uid()).ok(),
+        cgroup: fs::read_to_string(format!("{}/cgroup", proc_path)).ok().map(|cgroup| cgroup.trim().to_string()),
+        start_time,
+        uptime_seconds: process_uptime,
+        exited,
+        environment,
+    }
+}
+
+// --------------------------------------------------------------------
+// is_redacted - returns true if the value of the environment variable
+// must be redacted. Patterns are names, optionally ending with '*'.
+// --------------------------------------------------------------------
+fn is_redacted(name: &str, patterns: &[String]) -> bool
+{
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*')
+    {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    })
+}
+
+// --------------------------------------------------------------------
+// split_nul - Splits a NUL separated /proc file
+// --------------------------------------------------------------------
+fn split_nul(contents: &[u8]) -> Vec<String>
+{
+    contents.split(|c| *c == 0).filter(|s| !s.is_empty()).map(|s| String::from_utf8_lossy(s).to_string()).collect()
+}
+
+// --------------------------------------------------------------------
+// get_start_time - returns the wall clock time at which a process that
+// has been running for the given number of seconds was started
+// --------------------------------------------------------------------
+fn get_start_time(uptime: f64) -> Option<DateTime<Local>>
+{
+    let now = Local::now();
+    let start_millis = now.timestamp_millis() - (uptime * 1000.0) as i64;
+    Local.timestamp_millis_opt(start_millis).single()
+}
@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Dump file name templates, e.g. {name}_{trigger}_{timestamp}.{pid}
+//
+//   {pid}        process ID
+//   {name}       process name
+//   {trigger}    trigger type (cpu, memory, threads, ...)
+//   {counter}    number of dumps collected so far
+//   {timestamp}  local time, ISO 8601 without colons (20240131T154500)
+//   {epoch}      seconds since the epoch
+//   {hostname}   host name
+//   {uid}        user ID of the process
+//   {container}  container ID of the process ("none" outside containers)
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use chrono::Local;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+
+const TEMPLATE_TOKENS: [&str; 9] = ["pid", "name", "trigger", "counter", "timestamp", "epoch", "hostname", "uid", "container"];
+
+// --------------------------------------------------------------------
+// is_dump_name_template - returns true if the dump name is a template
+// --------------------------------------------------------------------
+pub fn is_dump_name_template(dump_name: &str) -> bool
+{
+    dump_name.contains('{')
+}
+
+// --------------------------------------------------------------------
+// validate_dump_name_template - Checks that all tokens of a template
+// are known
+// --------------------------------------------------------------------
+pub fn validate_dump_name_template(template: &str) -> Result<(), String>
+{
+    let mut rest = template;
+    while let Some(start) = rest.find('{')
+    {
+        let end = match rest[start..].find('}')
+        {
+            Some(end) => start + end,
+            None => return Err(format!("Unterminated token in dump name template '{}'.", template)),
+        };
+
+        let token = &rest[start + 1..end];
+        if !TEMPLATE_TOKENS.contains(&token)
+        {
+            return Err(format!("Unknown token '{{{}}}' in dump name template '{}'.", token, template));
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------------------------
+// expand_dump_name_template - returns the dump name for a template
+// --------------------------------------------------------------------
+pub fn expand_dump_name_template(template: &str, config: &ProcDumpConfiguration, trigger_type: &str) -> String
+{
+    let now = Local::now();
+    let mut name = template.to_string();
+
+    // Only look up what the template uses
+    for token in TEMPLATE_TOKENS
+    {
+        let placeholder = format!("{{{}}}", token);
+        if !name.contains(&placeholder)
+        {
+            continue;
+        }
+
+        let value = match token
+        {
+            "pid" => config.process_id.to_string(),
+            "name" => config.process_name.clone(),
+            "trigger" => trigger_type.to_string(),
+            "counter" => config.number_of_dumps_collected.to_string(),
+            "timestamp" => now.format("%Y%m%dT%H%M%S").to_string(),
+            "epoch" => now.timestamp().to_string(),
+            "hostname" => nix::unistd::gethostname().ok().and_then(|name| name.into_string().ok()).unwrap_or_default(),
+            "uid" => fs::metadata(format!("/proc/{}", config.process_id)).map(|metadata| metadata.uid().to_string()).unwrap_or_default(),
+            _ => get_container_id(config.process_id).unwrap_or_else(|| "none".to_string()),
+        };
+
+        name = name.replace(&placeholder, &sanitize(&value));
+    }
+
+    name
+}
+
+// --------------------------------------------------------------------
+// get_container_id - returns the container ID of a process, taken from
+// its cgroup (docker, containerd, cri-o and kubernetes layouts)
+// --------------------------------------------------------------------
+fn get_container_id(pid: i32) -> Option<String>
+{
+    let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+
+    // The container ID is a 64 hex digit path component, possibly with a
+    // prefix (docker-, cri-containerd-, crio-) and a .scope suffix
+    cgroup.lines()
+        .flat_map(|line| line.rsplit('/'))
+        .map(|component| component.trim_end_matches(".scope"))
+        .filter_map(|component| component.rsplit(['-', ':']).next())
+        .find(|id| id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|id| id.to_string())
+}
+
+// --------------------------------------------------------------------
+// sanitize - Replaces characters that are not safe in file names
+// (including on Windows shares and object stores)
+// --------------------------------------------------------------------
+fn sanitize(value: &str) -> String
+{
+    value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' }).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn validates_known_tokens()
+    {
+        assert_eq!(validate_dump_name_template("{name}_{trigger}_{timestamp}.{pid}"), Ok(()));
+        assert_eq!(validate_dump_name_template("{hostname}-{uid}-{container}-{epoch}-{counter}"), Ok(()));
+        assert_eq!(validate_dump_name_template("core"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_unterminated_tokens()
+    {
+        assert!(validate_dump_name_template("{name}_{pid").unwrap_err().starts_with("Unterminated token"));
+        assert!(validate_dump_name_template("{").unwrap_err().starts_with("Unterminated token"));
+    }
+
+    #[test]
+    fn rejects_unknown_tokens()
+    {
+        assert_eq!(validate_dump_name_template("{name}_{date}"), Err("Unknown token '{date}' in dump name template '{name}_{date}'.".to_string()));
+        assert!(validate_dump_name_template("{}").unwrap_err().starts_with("Unknown token '{}'"));
+        assert!(validate_dump_name_template("{na{me}").unwrap_err().starts_with("Unknown token '{na{me}'"));
+        assert!(validate_dump_name_template("{PID}").unwrap_err().starts_with("Unknown token '{PID}'"));
+    }
+
+    #[test]
+    fn sanitizes_values()
+    {
+        assert_eq!(sanitize("my-app_1.2"), "my-app_1.2");
+        assert_eq!(sanitize("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize("Web Content: (x)"), "Web_Content___x_");
+        assert_eq!(sanitize("café"), "caf_");
+    }
+
+    #[test]
+    fn expands_sanitized_values()
+    {
+        let config = ProcDumpConfiguration { process_id: 1234, process_name: "my app/1".to_string(), number_of_dumps_collected: 2, ..Default::default() };
+        assert_eq!(expand_dump_name_template("{name}_{trigger}.{pid}.{counter}", &config, "cpu"), "my_app_1_cpu.1234.2");
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Dump workers - monitors queue dump requests and keep sampling while
+// a bounded pool of workers writes the dumps
+//
+//--------------------------------------------------------------------
+use crate::dumpwriter::{TriggerContext, write_dump};
+use crate::events::{Event, emit};
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+struct DumpRequest
+{
+    config: Arc<Mutex<ProcDumpConfiguration>>,
+    trigger: TriggerContext,
+    result: Sender<bool>,
+}
+
+//
+// Dumps waiting for a worker and the processes being dumped. Only one
+// dump of a process is written at a time.
+//
+struct DumpQueue
+{
+    requests: VecDeque<DumpRequest>,
+    running: Vec<Arc<Mutex<ProcDumpConfiguration>>>,
+}
+
+static DUMP_QUEUE: Mutex<DumpQueue> = Mutex::new(DumpQueue { requests: VecDeque::new(), running: Vec::new() });
+static DUMP_QUEUE_CHANGED: Condvar = Condvar::new();
+
+// Set once dumps are cancelled, no further dumps are written
+static DUMPS_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+// --------------------------------------------------------------------
+// start_dump_workers - Starts the workers writing queued dumps, at most
+// that many dumps are written at the same time
+// --------------------------------------------------------------------
+pub fn start_dump_workers(count: u32) -> bool
+{
+    for i in 0..count
+    {
+        let thread = thread::Builder::new().name(format!("Dump worker thread {}", i)).spawn(dump_worker_thread);
+        if thread.is_err()
+        {
+            println!("Failed to start dump workers");
+            log(Severity::Error, "Failed to create dump worker thread");
+            return false;
+        }
+    }
+
+    log(Severity::Debug, &format!("Started {} dump worker(s)", count));
+    true
+}
+
+// --------------------------------------------------------------------
+// queue_dump - Reports the fired trigger and queues a dump of the
+// process. Returns a receiver for the result of the dump, or an error
+// if the dumps already queued for the process reach its number of dumps
+// to collect.
+// --------------------------------------------------------------------
+pub fn queue_dump(config: &Arc<Mutex<ProcDumpConfiguration>>, trigger: TriggerContext) -> Result<Receiver<bool>, String>
+{
+    let mut queue = DUMP_QUEUE.lock().unwrap();
+    if is_dump_cancelled()
+    {
+        return Err("Dumps have been cancelled".to_string());
+    }
+
+    let outstanding = queue.requests.iter().filter(|request| Arc::ptr_eq(&request.config, config)).count() + queue.running.iter().filter(|running| Arc::ptr_eq(running, config)).count();
+
+    let lock = config.lock().unwrap();
+    if lock.number_of_dumps_collected as usize + outstanding >= lock.number_of_dumps_to_collect as usize
+    {
+        return Err(format!("Process {} has already reached its number of dumps ({})", lock.process_id, lock.number_of_dumps_to_collect));
+    }
+
+    emit(Event::TriggerFired { pid: lock.process_id, process_name: &lock.process_name, trigger: &trigger.trigger_type, value: trigger.value, threshold: trigger.threshold });
+    log(Severity::Debug, &format!("Queued {} dump of process {} ({} queued)", trigger.trigger_type, lock.process_id, queue.requests.len() + 1));
+    drop(lock);
+
+    let (sender, receiver) = channel();
+    queue.requests.push_back(DumpRequest { config: config.clone(), trigger, result: sender });
+    DUMP_QUEUE_CHANGED.notify_all();
+
+    Ok(receiver)
+}
+
+// --------------------------------------------------------------------
+// wait_for_queued_dumps - Waits until all queued dumps are written
+// --------------------------------------------------------------------
+pub fn wait_for_queued_dumps()
+{
+    let mut queue = DUMP_QUEUE.lock().unwrap();
+    while !queue.requests.is_empty() || !queue.running.is_empty()
+    {
+        queue = DUMP_QUEUE_CHANGED.wait(queue).unwrap();
+    }
+}
+
+// --------------------------------------------------------------------
+// cancel_dumps - Cancels the dumps being written, fails the queued ones
+// and waits for the workers to clean up
+// --------------------------------------------------------------------
+pub fn cancel_dumps()
+{
+    let mut queue = DUMP_QUEUE.lock().unwrap();
+    DUMPS_CANCELLED.store(true, Ordering::SeqCst);
+    for request in queue.requests.drain(..)
+    {
+        let _ = request.result.send(false);
+    }
+
+    if !queue.running.is_empty()
+    {
+        println!("Cancelling {} dump(s) in progress", queue.running.len());
+        log(Severity::Warning, &format!("Cancelling {} dump(s) in progress", queue.running.len()));
+    }
+
+    DUMP_QUEUE_CHANGED.notify_all();
+    drop(queue);
+
+    wait_for_queued_dumps();
+}
+
+// --------------------------------------------------------------------
+// is_dump_cancelled - returns true if dumps have been cancelled, dumps
+// being written must then be stopped
+// --------------------------------------------------------------------
+pub fn is_dump_cancelled() -> bool
+{
+    DUMPS_CANCELLED.load(Ordering::SeqCst)
+}
+
+// --------------------------------------------------------------------
+// dump_worker_thread - Writes queued dumps, skipping the requests of
+// processes that are already being dumped by another worker
+// --------------------------------------------------------------------
+fn dump_worker_thread()
+{
+    loop
+    {
+        let mut queue = DUMP_QUEUE.lock().unwrap();
+        let request = loop
+        {
+            let next = queue.requests.iter().position(|request| !queue.running.iter().any(|running| Arc::ptr_eq(running, &request.config)));
+            match next
+            {
+                Some(index) => break queue.requests.remove(index).unwrap(),
+                None => queue = DUMP_QUEUE_CHANGED.wait(queue).unwrap(),
+            }
+        };
+
+        queue.running.push(request.config.clone());
+        drop(queue);
+
+        // A panic while writing must not leave the process marked as being dumped
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| write_dump(&request.config, &request.trigger)))
+        {
+            Ok(result) => result,
+            Err(_) =>
+            {
+                log(Severity::Error, &format!("Dump of process {} failed unexpectedly", request.config.lock().unwrap_or_else(PoisonError::into_inner).process_id));
+                false
+            },
+        };
+
+        let mut queue = DUMP_QUEUE.lock().unwrap();
+        queue.running.retain(|running| !Arc::ptr_eq(running, &request.config));
+        DUMP_QUEUE_CHANGED.notify_all();
+        drop(queue);
+
+        // Nobody may be waiting for the result
+        let _ = request.result.send(result);
+    }
+}
@@ -7,61 +7,473 @@
 //
 //--------------------------------------------------------------------
 extern crate chrono;
+extern crate nix;
 use chrono::Local;
-use crate::procdumpconfiguration::ProcDumpConfiguration;
+use crate::dumpcompressor::{compress_dump, get_compressed_extension};
+use crate::dumpmanifest::record_dump;
+use crate::dumpmetadata::write_dump_metadata;
+use crate::dumpname::{expand_dump_name_template, is_dump_name_template};
+use crate::dumpworker::is_dump_cancelled;
+use crate::events::{Event, emit};
+use crate::hooks::{run_post_dump_hook, run_pre_dump_hook};
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::{DumpCompression, ProcDumpConfiguration};
+use crate::processhelpers::{estimate_dump_size, is_process_stopped};
+use nix::sys::signal::{Signal, kill, killpg};
+use nix::sys::statvfs::statvfs;
+use nix::unistd::Pid;
+use serde::Serialize;
 use std::sync::{Arc, Mutex};
-use std::process::Command;
+use std::process::{self, Child, Command, Output, Stdio};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn write_dump(config: &Arc<Mutex<ProcDumpConfiguration>>, trigger_type: &String) -> bool
+const GCORE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+//
+// What caused a dump to be written
+//
+#[derive(Serialize)]
+pub struct TriggerContext
+{
+    #[serde(rename = "type")]
+    pub trigger_type: String,
+    pub value: Option<i64>,
+    pub threshold: Option<i64>,
+}
+
+// --------------------------------------------------------------------
+// check_free_space - Compares the estimated dump size and the free
+// space reserve with the free space of the dump folder. Compressed dumps
+// are written uncompressed first and the compressed copy is written next
+// to it, so the estimate counts twice for them.
+// --------------------------------------------------------------------
+fn check_free_space(config: &ProcDumpConfiguration) -> Result<(), String>
+{
+    let stats = match statvfs(config.core_dump_path.as_str())
+    {
+        Ok(stats) => stats,
+        Err(e) =>
+        {
+            log(Severity::Warning, &format!("Failed to get free space of {}: {}", config.core_dump_path, e));
+            return Ok(());
+        },
+    };
+
+    let free_space = stats.blocks_available() as u64 * stats.fragment_size() as u64;
+    let reserve = config.min_free_space * 1024 * 1024;
+    let mut estimate = estimate_dump_size(config.process_id).unwrap_or(0);
+    if config.dump_compression != DumpCompression::None
+    {
+        estimate *= 2;
+    }
+
+    log(Severity::Debug, &format!("Estimated dump size of process {} is {}MB, {}MB free in {} (reserve {}MB)", config.process_id, estimate >> 20, free_space >> 20, config.core_dump_path, config.min_free_space));
+    if estimate + reserve > free_space
+    {
+        return Err(format!("Skipping dump of process {}: estimated dump size {}MB plus reserve {}MB exceeds the {}MB free in {}", config.process_id, estimate >> 20, config.min_free_space, free_space >> 20, config.core_dump_path));
+    }
+
+    Ok(())
+}
+
+// --------------------------------------------------------------------
+// get_partial_dump_name - returns the hidden name a dump is written
+// under until it's complete, .<name>.procdump-<pid>.partial in the same
+// folder. The PID of the writing procdump tells if the dump is still
+// being written.
+// --------------------------------------------------------------------
+fn get_partial_dump_name(dump_path: &str) -> String
 {
-    let mut lock = config.lock().unwrap();
+    let path = Path::new(dump_path);
+    let directory = path.parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_else(|| ".".to_string());
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+    format!("{}/.{}.procdump-{}.partial", directory, file_name, process::id())
+}
+
+// --------------------------------------------------------------------
+// get_partial_dump_writer - returns the PID of the procdump writing the
+// partial dump (.<name>.procdump-<pid>.partial, or with a .<pid> suffix
+// for gcore), None if it's not the name of a partial dump
+// --------------------------------------------------------------------
+fn get_partial_dump_writer(file_name: &str) -> Option<i32>
+{
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+
+    let (head, suffix) = file_name.strip_prefix('.')?.rsplit_once(".partial")?;
+    if !suffix.is_empty() && !suffix.strip_prefix('.').is_some_and(is_number)
+    {
+        return None;
+    }
+
+    let (_, writer) = head.rsplit_once(".procdump-")?;
+    if !is_number(writer)
+    {
+        return None;
+    }
+
+    writer.parse::<i32>().ok()
+}
+
+// --------------------------------------------------------------------
+// is_procdump_running - returns true if the process is a procdump other
+// than this one
+// --------------------------------------------------------------------
+fn is_procdump_running(pid: i32) -> bool
+{
+    let own_name = fs::read_to_string("/proc/self/comm").unwrap_or_default();
+    match fs::read_to_string(format!("/proc/{}/comm", pid))
+    {
+        Ok(name) => pid as u32 != process::id() && name == own_name,
+        Err(_) => false,
+    }
+}
+
+// --------------------------------------------------------------------
+// remove_partial_dumps - Removes the partial dumps left in the dump
+// folders of the profiles by a procdump that didn't finish its dumps.
+// Partial dumps of a procdump that is still running are left alone.
+// --------------------------------------------------------------------
+pub fn remove_partial_dumps(profiles: &[ProcDumpConfiguration])
+{
+    let mut directories: Vec<&String> = profiles.iter().map(|profile| &profile.core_dump_path).collect();
+    directories.sort();
+    directories.dedup();
+
+    for directory in directories
+    {
+        let entries = match fs::read_dir(directory)
+        {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten()
+        {
+            let writer = match get_partial_dump_writer(&entry.file_name().to_string_lossy())
+            {
+                Some(writer) => writer,
+                None => continue,
+            };
+
+            let path = entry.path();
+            if is_procdump_running(writer)
+            {
+                log(Severity::Debug, &format!("Keeping partial dump {} of running procdump {}", path.display(), writer));
+                continue;
+            }
+
+            match fs::remove_file(&path)
+            {
+                Ok(_) => log(Severity::Info, &format!("Removed partial dump {}", path.display())),
+                Err(e) => log(Severity::Warning, &format!("Failed to remove partial dump {}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// publish_dump - Checks that the partial dump is a complete core file
+// (or compressed stream) and renames it to its final name
+// --------------------------------------------------------------------
+fn publish_dump(partial_dump_name: &str, dump_path: &str, compression: DumpCompression) -> Result<(), String>
+{
+    let magic: &[u8] = match compression
+    {
+        DumpCompression::None => b"\x7fELF",
+        DumpCompression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+        DumpCompression::Gzip => &[0x1f, 0x8b],
+    };
+
+    let mut header = vec![0u8; magic.len()];
+    let valid = File::open(partial_dump_name).and_then(|mut file| file.read_exact(&mut header)).is_ok() && header == magic;
+    if !valid
+    {
+        return Err(format!("Dump {} is empty or not a valid {} file", dump_path, if compression == DumpCompression::None { "core" } else { compression.as_str() }));
+    }
+
+    fs::rename(partial_dump_name, dump_path).map_err(|e| format!("Failed to rename dump {} to {}: {}", partial_dump_name, dump_path, e))
+}
+
+// --------------------------------------------------------------------
+// run_gcore - Runs gcore and waits for it. If the dump times out or is
+// cancelled, gcore and the gdb it started are killed and the reason is
+// returned.
+// --------------------------------------------------------------------
+fn run_gcore(config: &ProcDumpConfiguration, gcore_prefix_name: &str) -> Result<Output, String>
+{
+    let was_stopped = is_process_stopped(config.process_id);
+
+    // Own process group so gcore and gdb can be killed together
+    log(Severity::Info, &format!("Running gcore -o {} {}", gcore_prefix_name, config.process_id));
+    let mut child = match Command::new("gcore").arg("-o").arg(gcore_prefix_name).arg(config.process_id.to_string()).process_group(0).stdout(Stdio::null()).stderr(Stdio::piped()).spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return Err(format!("Failed to execute gcore: {}", e)),
+    };
+
+    // Drain stderr while waiting so gcore can't block on a full pipe
+    let reader = child.stderr.take().map(|mut stderr| thread::spawn(move ||
+    {
+        let mut output = Vec::new();
+        let _ = stderr.read_to_end(&mut output);
+        output
+    }));
+
+    let start = Instant::now();
+    loop
+    {
+        let reason = match child.try_wait()
+        {
+            Ok(Some(status)) =>
+            {
+                log(Severity::Info, &format!("gcore for process {} exited with {} after {}ms", config.process_id, status, start.elapsed().as_millis()));
+                let stderr = reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+                return Ok(Output { status, stdout: Vec::new(), stderr });
+            },
+            Ok(None) if is_dump_cancelled() => format!("Dump of process {} was cancelled", config.process_id),
+            Ok(None) if start.elapsed().as_secs() >= config.dump_timeout => format!("Dump of process {} timed out after {}s", config.process_id, config.dump_timeout),
+            Ok(None) =>
+            {
+                thread::sleep(GCORE_POLL_INTERVAL);
+                continue;
+            },
+            Err(e) => format!("Failed to wait for gcore: {}", e),
+        };
+
+        kill_gcore(&mut child, config, was_stopped);
+        return Err(reason);
+    }
+}
+
+// --------------------------------------------------------------------
+// kill_gcore - Kills gcore and gdb. gdb's death detaches it from the
+// target, which is resumed if it was left stopped.
+// --------------------------------------------------------------------
+fn kill_gcore(child: &mut Child, config: &ProcDumpConfiguration, was_stopped: bool)
+{
+    let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+    let _ = child.wait();
+
+    if !was_stopped && is_process_stopped(config.process_id)
+    {
+        log(Severity::Warning, &format!("Resuming process {} left stopped by gcore", config.process_id));
+        let _ = match &config.process_handle
+        {
+            Some(process_handle) => process_handle.send_signal(Signal::SIGCONT),
+            None => kill(Pid::from_raw(config.process_id), Signal::SIGCONT),
+        };
+    }
+}
+
+// --------------------------------------------------------------------
+// write_dump - Writes a dump of the process, called by the dump workers.
+// Returns true if the dump was written.
+// --------------------------------------------------------------------
+pub fn write_dump(config: &Arc<Mutex<ProcDumpConfiguration>>, trigger: &TriggerContext) -> bool
+{
+    // Work on a snapshot so the monitors aren't blocked while the dump is written
+    let lock = config.lock().unwrap();
+    if lock.number_of_dumps_collected >= lock.number_of_dumps_to_collect
+    {
+        log(Severity::Debug, &format!("Dropping {} dump of process {}, its number of dumps has been reached", trigger.trigger_type, lock.process_id));
+        return false;
+    }
+    let snapshot = lock.clone();
+    drop(lock);
+    let trigger_type = &trigger.trigger_type;
 
     // Get current date
     let current = Local::now();
     let dump_date = current.format("%Y-%m-%d_%H:%M:%S").to_string();
 
-    // Construct the dump prefix
-    let gcore_prefix_name: String;
-    if !lock.core_dump_name.is_empty()
+    // Construct the (uncompressed) dump file name
+    let dump_file_name: String;
+    if is_dump_name_template(&snapshot.core_dump_name)
     {
-        gcore_prefix_name = format!("{}/{}_{}", lock.core_dump_path, lock.core_dump_name, lock.number_of_dumps_collected);
+        dump_file_name = format!("{}/{}", snapshot.core_dump_path, expand_dump_name_template(&snapshot.core_dump_name, &snapshot, trigger_type));
+    }
+    else if !snapshot.core_dump_name.is_empty()
+    {
+        dump_file_name = format!("{}/{}_{}.{}", snapshot.core_dump_path, snapshot.core_dump_name, snapshot.number_of_dumps_collected, snapshot.process_id);
     }
     else
     {
-        gcore_prefix_name = format!("{}/{}_{}_{}", lock.core_dump_path, lock.process_name, trigger_type, dump_date);
+        dump_file_name = format!("{}/{}_{}_{}.{}", snapshot.core_dump_path, snapshot.process_name, trigger_type, dump_date, snapshot.process_id);
     }
 
-    // Construct the dump file name
-    let core_dump_file_name = format!("{}.{}", gcore_prefix_name, lock.process_id);
-    //let core_dump_name = gcore_prefix_name.clone();
+    let core_dump_file_name = format!("{}{}", dump_file_name, get_compressed_extension(snapshot.dump_compression));
+
+    // The dump is written under a hidden partial name and only renamed once
+    // it's complete. gcore writes <prefix>.<pid>, with compression that is
+    // then compressed into the partial file and removed.
+    let gcore_prefix_name = get_partial_dump_name(&core_dump_file_name);
+    let gcore_output_name = format!("{}.{}", gcore_prefix_name, snapshot.process_id);
+    let partial_dump_name = if snapshot.dump_compression != DumpCompression::None { gcore_prefix_name.clone() } else { gcore_output_name.clone() };
 
     // Check if file already exists and if we have the overwrite flag set
-    if Path::new(&core_dump_file_name).exists() && !lock.overwrite_existing_dump
+    if Path::new(&core_dump_file_name).exists() && !snapshot.overwrite_existing_dump
+    {
+        let reason = format!("Dump file {} already exists and was not overwritten (use -o to overwrite)", core_dump_file_name);
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: 0 });
+        return false;
+    }
+
+    // Make sure the dump fits without eating into the free space reserve
+    if let Err(reason) = check_free_space(&snapshot)
     {
-        println!("Dump file {} already exists and was not overwritten (use -o to overwrite)", core_dump_file_name);
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: 0 });
+        return false;
+    }
+
+    // Give the target a chance to prepare for, or veto, the dump
+    let pre_dump_output = match run_pre_dump_hook(&snapshot, trigger, &core_dump_file_name, snapshot.number_of_dumps_collected)
+    {
+        Ok(output) => output,
+        Err(reason) =>
+        {
+            emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: 0 });
+            return false;
+        },
+    };
+
+    emit(Event::DumpStarted { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name });
+    let dump_start = Instant::now();
+
+    // gcore attaches by PID, make sure it still refers to the monitored process
+    let target_exited = || snapshot.process_handle.as_ref().is_some_and(|process_handle| process_handle.has_exited());
+    if target_exited()
+    {
+        let reason = format!("Process {} has exited, its PID may have been reused", snapshot.process_id);
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
         return false;
     }
 
     // Run gcore
-    let gcore_res = Command::new("gcore").arg("-o").arg(gcore_prefix_name).arg(lock.process_id.to_string()).output().expect("Failed to execute gcore.");
-    //let gcore_stdout = gcore_res.stdout;
+    let gcore_res = match run_gcore(&snapshot, &gcore_prefix_name)
+    {
+        Ok(output) => output,
+        Err(reason) =>
+        {
+            // Remove the partial dump
+            let _ = fs::remove_file(&gcore_output_name);
+
+            emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
+            return false;
+        },
+    };
     let gcore_stderr = gcore_res.stderr;
 
+    // The target may exit (crash, OOM kill) while or right after it's dumped, the dump is kept.
+    // If its PID now belongs to another process gcore may have attached to that one instead.
+    let process_exited = target_exited();
+    if process_exited && snapshot.process_handle.as_ref().is_some_and(|process_handle| process_handle.is_pid_reused())
+    {
+        let _ = fs::remove_file(&gcore_output_name);
+
+        let reason = format!("Process {} exited while it was being dumped and its PID was reused", snapshot.process_id);
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
+        return false;
+    }
+
+    // Compress the dump written by gcore, the uncompressed dump is removed either way
+    let compression = if gcore_res.status.success() && snapshot.dump_compression != DumpCompression::None
+    {
+        let result = compress_dump(&gcore_output_name, &partial_dump_name, snapshot.dump_compression);
+        let _ = fs::remove_file(&gcore_output_name);
+        Some(result)
+    }
+    else
+    {
+        None
+    };
+
     // If we failed, dump error
     if !gcore_res.status.success()
     {
-        println!("Failed to generate dump");
-        let res_stdout = str::from_utf8(&gcore_stderr);
-        print!("GCORE - {}", res_stdout.unwrap());
+        let _ = fs::remove_file(&gcore_output_name);
+
+        let res_stdout = String::from_utf8_lossy(&gcore_stderr);
+        let reason = format!("Failed to generate dump\nGCORE - {}", res_stdout.trim_end());
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
+        return false;
+    }
+    else if let Some(Err(e)) = &compression
+    {
+        let _ = fs::remove_file(&partial_dump_name);
+
+        let reason = format!("Failed to compress dump {}: {}", core_dump_file_name, e);
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
+        return false;
+    }
+    else if let Err(reason) = publish_dump(&partial_dump_name, &core_dump_file_name, snapshot.dump_compression)
+    {
+        let _ = fs::remove_file(&partial_dump_name);
+
+        emit(Event::DumpFailed { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, reason: &reason, duration_ms: dump_start.elapsed().as_millis() });
         return false;
     }
     else
     {
-        println!("Core dump {} generated: {}", lock.number_of_dumps_collected, core_dump_file_name);
-        lock.number_of_dumps_collected += 1;
+        let gcore_duration_ms = dump_start.elapsed().as_millis();
+        let compression = compression.and_then(|result| result.ok());
+        if process_exited
+        {
+            log(Severity::Warning, &format!("Process {} exited while it was being dumped, keeping dump {}", snapshot.process_id, core_dump_file_name));
+        }
+
+        if !write_dump_metadata(&snapshot, trigger, &core_dump_file_name, gcore_duration_ms, compression.as_ref(), pre_dump_output.as_deref(), process_exited)
+        {
+            log(Severity::Warning, &format!("Failed to write metadata file {}.json", core_dump_file_name));
+        }
+
+        emit(Event::DumpSucceeded { pid: snapshot.process_id, process_name: &snapshot.process_name, trigger: trigger_type, dump_path: &core_dump_file_name, dump_counter: snapshot.number_of_dumps_collected, duration_ms: gcore_duration_ms, compression: compression.as_ref() });
+        record_dump(&snapshot, &core_dump_file_name, trigger_type);
+        run_post_dump_hook(&snapshot, trigger, &core_dump_file_name, snapshot.number_of_dumps_collected);
+        config.lock().unwrap().number_of_dumps_collected += 1;
     }
 
     true
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn gets_writer_of_partial_dump()
+    {
+        assert_eq!(get_partial_dump_writer(".app_cpu_2026-10-18_20:06:48.1234.procdump-5678.partial"), Some(5678));
+
+        let partial_dump_name = get_partial_dump_name("/var/dumps/app_cpu_2026-10-18_20:06:48.1234");
+        let file_name = Path::new(&partial_dump_name).file_name().unwrap().to_str().unwrap();
+        assert_eq!(get_partial_dump_writer(file_name), Some(process::id() as i32));
+    }
+
+    #[test]
+    fn gets_writer_of_partial_gcore_output()
+    {
+        // gcore appends the PID of the target to the name it's given
+        assert_eq!(get_partial_dump_writer(".app_cpu_2026-10-18_20:06:48.1234.procdump-5678.partial.1234"), Some(5678));
+        assert_eq!(get_partial_dump_writer(".app.procdump-5678.partial.x"), None);
+        assert_eq!(get_partial_dump_writer(".app.procdump-5678.partial."), None);
+    }
+
+    #[test]
+    fn ignores_other_files()
+    {
+        assert_eq!(get_partial_dump_writer("app_cpu_2026-10-18_20:06:48.1234"), None);
+        assert_eq!(get_partial_dump_writer("app.procdump-5678.partial"), None);
+        assert_eq!(get_partial_dump_writer(".app.partial"), None);
+        assert_eq!(get_partial_dump_writer(".app.procdump-.partial"), None);
+        assert_eq!(get_partial_dump_writer(".app.procdump-56a8.partial"), None);
+        assert_eq!(get_partial_dump_writer(".procdump_manifest.jsonl"), None);
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Reports procdump events on the console and, in JSON mode, as one
+// JSON object per line on stdout or a file
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::dumpcompressor::CompressionStats;
+use crate::logging::{Severity, log};
+use crate::metrics::record_event;
+use crate::procdumpconfiguration::{ProcDumpConfiguration, print_configuration};
+use chrono::Local;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::sync::Mutex;
+
+// Destination of JSON events, None when JSON mode is off
+static JSON_OUTPUT: Mutex<Option<File>> = Mutex::new(None);
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a>
+{
+    Config { config: &'a ProcDumpConfiguration },
+    MonitorStarted { pid: i32, process_name: &'a str },
+    MonitorStopped { pid: i32, process_name: &'a str },
+    TriggerFired { pid: i32, process_name: &'a str, trigger: &'a str, value: Option<i64>, threshold: Option<i64> },
+    DumpStarted { pid: i32, process_name: &'a str, trigger: &'a str, dump_path: &'a str },
+    DumpSucceeded { pid: i32, process_name: &'a str, trigger: &'a str, dump_path: &'a str, dump_counter: u32, duration_ms: u128, compression: Option<&'a CompressionStats> },
+    DumpFailed { pid: i32, process_name: &'a str, trigger: &'a str, dump_path: &'a str, reason: &'a str, duration_ms: u128 },
+    ProcessExited { pid: i32, process_name: &'a str },
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a>
+{
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+// --------------------------------------------------------------------
+// init_events - Enables JSON output if requested by the configuration.
+// When JSON goes to stdout, the console output is moved to stderr so
+// that stdout only contains events.
+// --------------------------------------------------------------------
+pub fn init_events(config: &ProcDumpConfiguration) -> bool
+{
+    if !config.json_output
+    {
+        return true;
+    }
+
+    let output = if !config.json_output_path.is_empty()
+    {
+        match OpenOptions::new().create(true).append(true).open(&config.json_output_path)
+        {
+            Ok(file) => file,
+            Err(e) =>
+            {
+                println!("Failed to open JSON output file {}: {}", config.json_output_path, e);
+                return false;
+            },
+        }
+    }
+    else
+    {
+        let stdout_fd = match nix::unistd::dup(1)
+        {
+            Ok(fd) => fd,
+            Err(_) => return false,
+        };
+
+        if nix::unistd::dup2(2, 1).is_err()
+        {
+            return false;
+        }
+
+        // We own the duplicated descriptor
+        unsafe { File::from_raw_fd(stdout_fd) }
+    };
+
+    *JSON_OUTPUT.lock().unwrap() = Some(output);
+    true
+}
+
+// --------------------------------------------------------------------
+// emit - Reports an event
+// --------------------------------------------------------------------
+pub fn emit(event: Event)
+{
+    // Console and diagnostic tracing
+    match &event
+    {
+        Event::Config { config } => print_configuration(config),
+        _ =>
+        {
+            if let Some(text) = event_text(&event)
+            {
+                println!("{}", text);
+
+                let severity = match &event { Event::DumpFailed { .. } => Severity::Error, _ => Severity::Info };
+                log(severity, &text);
+            }
+        },
+    }
+
+    record_event(&event);
+
+    // JSON
+    let mut json_output = JSON_OUTPUT.lock().unwrap();
+    if let Some(output) = json_output.as_mut()
+    {
+        let record = EventRecord
+        {
+            timestamp: Local::now().to_rfc3339(),
+            event: &event,
+        };
+
+        if let Ok(line) = serde_json::to_string(&record)
+        {
+            let _ = writeln!(output, "{}", line);
+            let _ = output.flush();
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// event_text - Returns the console text of an event, if any
+// --------------------------------------------------------------------
+fn event_text(event: &Event) -> Option<String>
+{
+    match event
+    {
+        Event::Config { .. } => None,
+        Event::MonitorStarted { pid, process_name } => Some(format!("Starting monitor for process {} ({})", process_name, pid)),
+        Event::MonitorStopped { pid, process_name } => Some(format!("Stopping monitor for process {} ({})", process_name, pid)),
+        Event::TriggerFired { pid, trigger, value, .. } =>
+        {
+            let value = value.unwrap_or_default();
+            match *trigger
+            {
+                "cpu" => Some(format!("Trigger: CPU usage:{}% on process ID: {}", value, pid)),
+                "memory" => Some(format!("Trigger: Commit usage:{}MB on process ID: {}", value, pid)),
+                "threads" => Some(format!("Trigger: Thread count:{} on process ID: {}", value, pid)),
+                "file_descriptor" => Some(format!("Trigger: File descriptors:{} on process ID: {}", value, pid)),
+                "timer" => Some(format!("Trigger: Timer:{}(ms) on process ID: {}", value, pid)),
+                "manual" => Some(format!("Trigger: Manual dump on process ID: {}", pid)),
+                _ => Some(format!("Trigger: {} on process ID: {}", trigger, pid)),
+            }
+        },
+        Event::DumpStarted { .. } => None,
+        Event::DumpSucceeded { dump_path, dump_counter, compression: None, .. } => Some(format!("Core dump {} generated: {}", dump_counter, dump_path)),
+        Event::DumpSucceeded { dump_path, dump_counter, compression: Some(stats), .. } => Some(format!("Core dump {} generated: {} ({} compressed, {} -> {} bytes, ratio {:.1}:1)", dump_counter, dump_path, stats.algorithm.as_str(), stats.uncompressed_size, stats.compressed_size, stats.ratio)),
+        Event::DumpFailed { reason, .. } => Some(reason.to_string()),
+        Event::ProcessExited { pid, .. } => Some(format!("Target process {} is no longer alive", pid)),
+    }
+}
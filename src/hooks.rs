@@ -0,0 +1,173 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// User commands run around dumps (-pre-cmd and -post-cmd)
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::dumpworker::is_dump_cancelled;
+use crate::dumpwriter::TriggerContext;
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use nix::sys::signal::{Signal, killpg};
+use nix::unistd::Pid;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// --------------------------------------------------------------------
+// run_pre_dump_hook - Runs the pre dump command before gcore and waits
+// for it. Returns the command's stdout, None if no command is set, or
+// the reason the dump is vetoed if the command exited with non-zero or
+// dumps were cancelled. A command that can't run or times out doesn't
+// prevent the dump.
+// --------------------------------------------------------------------
+pub fn run_pre_dump_hook(config: &ProcDumpConfiguration, trigger: &TriggerContext, dump_path: &str, dump_counter: u32) -> Result<Option<String>, String>
+{
+    if config.pre_dump_command.is_empty()
+    {
+        return Ok(None);
+    }
+
+    let environment = get_hook_environment(config, trigger, dump_path, dump_counter);
+    let (status, output) = run_hook("Pre dump", &config.pre_dump_command, &environment, Duration::from_secs(config.pre_dump_timeout), true, true);
+    match status
+    {
+        _ if is_dump_cancelled() => Err(format!("Skipping dump of process {}: dumps were cancelled", config.process_id)),
+        Some(status) if !status.success() => Err(format!("Skipping dump of process {}: pre dump command {}", config.process_id, status)),
+        _ => Ok(Some(output)),
+    }
+}
+
+// --------------------------------------------------------------------
+// run_post_dump_hook - Runs the post dump command for a dump that was
+// just written. Unless configured to wait, the command runs in the
+// background so the monitor isn't blocked.
+// --------------------------------------------------------------------
+pub fn run_post_dump_hook(config: &ProcDumpConfiguration, trigger: &TriggerContext, dump_path: &str, dump_counter: u32)
+{
+    if config.post_dump_command.is_empty()
+    {
+        return;
+    }
+
+    let environment = get_hook_environment(config, trigger, dump_path, dump_counter);
+
+    let command = config.post_dump_command.clone();
+    let timeout = Duration::from_secs(config.post_dump_timeout);
+    if config.post_dump_wait
+    {
+        run_hook("Post dump", &command, &environment, timeout, false, true);
+    }
+    else
+    {
+        let thread = thread::Builder::new().name("Post dump hook thread".to_string()).spawn(move ||
+        {
+            run_hook("Post dump", &command, &environment, timeout, false, false);
+        });
+
+        if thread.is_err()
+        {
+            log(Severity::Error, "Failed to create post dump hook thread");
+        }
+    }
+}
+
+// --------------------------------------------------------------------
+// get_hook_environment - Returns the PROCDUMP_* variables describing
+// the dump that are passed to hook commands
+// --------------------------------------------------------------------
+fn get_hook_environment(config: &ProcDumpConfiguration, trigger: &TriggerContext, dump_path: &str, dump_counter: u32) -> Vec<(&'static str, String)>
+{
+    vec![
+        ("PROCDUMP_DUMP_PATH", dump_path.to_string()),
+        ("PROCDUMP_PID", config.process_id.to_string()),
+        ("PROCDUMP_PROCESS_NAME", config.process_name.clone()),
+        ("PROCDUMP_TRIGGER", trigger.trigger_type.clone()),
+        ("PROCDUMP_VALUE", trigger.value.map_or(String::new(), |v| v.to_string())),
+        ("PROCDUMP_THRESHOLD", trigger.threshold.map_or(String::new(), |v| v.to_string())),
+        ("PROCDUMP_DUMP_COUNTER", dump_counter.to_string()),
+    ]
+}
+
+// --------------------------------------------------------------------
+// run_hook - Runs a command through the shell and waits for it, killing
+// it (and everything it started) once the timeout expires, or when dumps
+// are cancelled if the command blocks a dump. Returns the exit status,
+// None if the command couldn't run, timed out or was cancelled, and the
+// command's stdout if it's captured.
+// --------------------------------------------------------------------
+fn run_hook(hook_name: &str, command: &str, environment: &[(&str, String)], timeout: Duration, capture_output: bool, cancellable: bool) -> (Option<ExitStatus>, String)
+{
+    log(Severity::Info, &format!("{} command: {}", hook_name, command));
+
+    // Own process group so the whole command can be killed on timeout
+    let mut child = match Command::new("/bin/sh").arg("-c").arg(command).envs(environment.iter().map(|(name, value)| (*name, value))).process_group(0).stdout(if capture_output { Stdio::piped() } else { Stdio::inherit() }).spawn()
+    {
+        Ok(child) => child,
+        Err(e) =>
+        {
+            println!("{} command failed to start: {}", hook_name, e);
+            log(Severity::Error, &format!("{} command failed to start: {}", hook_name, e));
+            return (None, String::new());
+        },
+    };
+
+    // Drain stdout while waiting so the command can't block on a full pipe
+    let reader = child.stdout.take().map(|mut stdout| thread::spawn(move ||
+    {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        String::from_utf8_lossy(&output).to_string()
+    }));
+    let collect_output = |reader: Option<thread::JoinHandle<String>>| reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+
+    let start = Instant::now();
+    loop
+    {
+        match child.try_wait()
+        {
+            Ok(Some(status)) =>
+            {
+                let severity = if status.success() { Severity::Info } else { Severity::Warning };
+                if !status.success()
+                {
+                    println!("{} command {}", hook_name, status);
+                }
+
+                log(severity, &format!("{} command {} after {}ms", hook_name, status, start.elapsed().as_millis()));
+                return (Some(status), collect_output(reader));
+            },
+            Ok(None) if start.elapsed() >= timeout =>
+            {
+                let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+                let _ = child.wait();
+
+                println!("{} command timed out after {}s and was killed", hook_name, timeout.as_secs());
+                log(Severity::Warning, &format!("{} command timed out after {}s and was killed", hook_name, timeout.as_secs()));
+                return (None, collect_output(reader));
+            },
+            Ok(None) if cancellable && is_dump_cancelled() =>
+            {
+                let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+                let _ = child.wait();
+
+                println!("{} command was killed, dumps were cancelled", hook_name);
+                log(Severity::Warning, &format!("{} command was killed, dumps were cancelled", hook_name));
+                return (None, collect_output(reader));
+            },
+            Ok(None) => thread::sleep(HOOK_POLL_INTERVAL),
+            Err(e) =>
+            {
+                log(Severity::Error, &format!("Failed to wait for {} command: {}", hook_name, e));
+                return (None, String::new());
+            },
+        }
+    }
+}
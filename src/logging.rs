@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Diagnostic tracing (-log) to journald or syslog
+//
+//--------------------------------------------------------------------
+use chrono::Local;
+use std::os::unix::net::UnixDatagram;
+use std::sync::Mutex;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+const SYSLOG_IDENTIFIER: &str = "procdump";
+const SYSLOG_FACILITY_USER: u8 = 1;
+
+//
+// Severity levels (values match syslog priorities)
+//
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Severity
+{
+    Error = 3,
+    Warning = 4,
+    Info = 6,
+    Debug = 7,
+}
+
+enum LogTarget
+{
+    Journald,
+    Syslog,
+}
+
+struct Logger
+{
+    socket: UnixDatagram,
+    target: LogTarget,
+}
+
+// Logger shared by all threads, None while tracing is disabled
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+// --------------------------------------------------------------------
+// enable_logging - Enables diagnostic tracing, preferring journald's
+// native protocol and falling back to syslog. Enabling it more than
+// once has no effect.
+// --------------------------------------------------------------------
+pub fn enable_logging() -> bool
+{
+    let mut logger = LOGGER.lock().unwrap();
+    if logger.is_some()
+    {
+        return true;
+    }
+
+    let socket = match UnixDatagram::unbound()
+    {
+        Ok(socket) => socket,
+        Err(_) => return false,
+    };
+
+    if socket.connect(JOURNALD_SOCKET).is_ok()
+    {
+        *logger = Some(Logger { socket, target: LogTarget::Journald });
+    }
+    else if socket.connect(SYSLOG_SOCKET).is_ok()
+    {
+        *logger = Some(Logger { socket, target: LogTarget::Syslog });
+    }
+    else
+    {
+        println!("Failed to connect to journald or syslog, diagnostic tracing is disabled.");
+        return false;
+    }
+
+    true
+}
+
+// --------------------------------------------------------------------
+// is_logging_enabled - returns true if diagnostic tracing is enabled
+// --------------------------------------------------------------------
+pub fn is_logging_enabled() -> bool
+{
+    LOGGER.lock().unwrap().is_some()
+}
+
+// --------------------------------------------------------------------
+// log - Writes a trace message if diagnostic tracing is enabled
+// --------------------------------------------------------------------
+pub fn log(severity: Severity, message: &str)
+{
+    let logger = LOGGER.lock().unwrap();
+    let logger = match logger.as_ref()
+    {
+        Some(logger) => logger,
+        None => return,
+    };
+
+    let datagram = match logger.target
+    {
+        LogTarget::Journald => journald_datagram(severity, message),
+        LogTarget::Syslog => syslog_datagram(severity, message),
+    };
+
+    // Tracing must never get in the way of monitoring
+    let _ = logger.socket.send(&datagram);
+}
+
+// --------------------------------------------------------------------
+// journald_datagram - Formats a message using journald's native protocol
+// --------------------------------------------------------------------
+fn journald_datagram(severity: Severity, message: &str) -> Vec<u8>
+{
+    let mut datagram = Vec::new();
+    append_journald_field(&mut datagram, "PRIORITY", &(severity as u8).to_string());
+    append_journald_field(&mut datagram, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    append_journald_field(&mut datagram, "SYSLOG_PID", &std::process::id().to_string());
+    append_journald_field(&mut datagram, "MESSAGE", message);
+
+    datagram
+}
+
+// --------------------------------------------------------------------
+// append_journald_field - Appends a field, using the length prefixed
+// form for values spanning multiple lines
+// --------------------------------------------------------------------
+fn append_journald_field(datagram: &mut Vec<u8>, name: &str, value: &str)
+{
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains('\n')
+    {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    }
+    else
+    {
+        datagram.push(b'=');
+    }
+
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
+
+// --------------------------------------------------------------------
+// syslog_datagram - Formats a message as a (RFC 3164) syslog message
+// --------------------------------------------------------------------
+fn syslog_datagram(severity: Severity, message: &str) -> Vec<u8>
+{
+    let priority = (SYSLOG_FACILITY_USER << 3) | severity as u8;
+    format!("<{}>{} {}[{}]: {}", priority, Local::now().format("%b %e %H:%M:%S"), SYSLOG_IDENTIFIER, std::process::id(), message.replace('\n', " ")).into_bytes()
+}
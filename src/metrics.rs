@@ -0,0 +1,262 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Prometheus metrics endpoint (-metrics). Serves the latest samples of
+// the monitored processes and procdump's own counters.
+//
+//--------------------------------------------------------------------
+use crate::events::Event;
+use crate::logging::{Severity, log};
+use crate::monitor::{ProfileMonitorList, is_monitor_finished};
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Upper bounds (seconds) of the dump duration histogram buckets
+const DUMP_DURATION_BUCKETS: [f64; 9] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0];
+
+//
+// procdump's own counters, labelled by trigger type
+//
+struct Counters
+{
+    triggers_fired: BTreeMap<String, u64>,
+    dumps_collected: BTreeMap<String, u64>,
+    dumps_failed: BTreeMap<String, u64>,
+    dump_duration_buckets: [u64; DUMP_DURATION_BUCKETS.len()],
+    dump_duration_sum: f64,
+    dump_duration_count: u64,
+}
+
+static COUNTERS: Mutex<Counters> = Mutex::new(Counters
+{
+    triggers_fired: BTreeMap::new(),
+    dumps_collected: BTreeMap::new(),
+    dumps_failed: BTreeMap::new(),
+    dump_duration_buckets: [0; DUMP_DURATION_BUCKETS.len()],
+    dump_duration_sum: 0.0,
+    dump_duration_count: 0,
+});
+
+// --------------------------------------------------------------------
+// record_event - Updates the counters based on a procdump event
+// --------------------------------------------------------------------
+pub fn record_event(event: &Event)
+{
+    let mut counters = COUNTERS.lock().unwrap();
+    match event
+    {
+        Event::TriggerFired { trigger, .. } =>
+        {
+            *counters.triggers_fired.entry(trigger.to_string()).or_default() += 1;
+        },
+        Event::DumpSucceeded { trigger, duration_ms, .. } =>
+        {
+            *counters.dumps_collected.entry(trigger.to_string()).or_default() += 1;
+
+            let seconds = *duration_ms as f64 / 1000.0;
+            for (i, bound) in DUMP_DURATION_BUCKETS.iter().enumerate()
+            {
+                if seconds <= *bound
+                {
+                    counters.dump_duration_buckets[i] += 1;
+                }
+            }
+            counters.dump_duration_sum += seconds;
+            counters.dump_duration_count += 1;
+        },
+        Event::DumpFailed { trigger, .. } =>
+        {
+            *counters.dumps_failed.entry(trigger.to_string()).or_default() += 1;
+        },
+        _ => {},
+    }
+}
+
+// --------------------------------------------------------------------
+// start_metrics_server - Serves /metrics on the specified address
+// (e.g. 127.0.0.1:9400). Returns false if the address can't be bound.
+// --------------------------------------------------------------------
+pub fn start_metrics_server(address: &String, profile_monitors: ProfileMonitorList) -> bool
+{
+    let listener = match TcpListener::bind(address)
+    {
+        Ok(listener) => listener,
+        Err(e) =>
+        {
+            println!("Failed to start metrics endpoint on {}: {}", address, e);
+            log(Severity::Error, &format!("Failed to start metrics endpoint on {}: {}", address, e));
+            return false;
+        },
+    };
+
+    let thread = thread::Builder::new().name("Metrics server thread".to_string()).spawn(move ||
+    {
+        for stream in listener.incoming().flatten()
+        {
+            handle_request(stream, &profile_monitors);
+        }
+    });
+
+    thread.is_ok()
+}
+
+// --------------------------------------------------------------------
+// handle_request - Answers a single HTTP request
+// --------------------------------------------------------------------
+fn handle_request(mut stream: TcpStream, profile_monitors: &ProfileMonitorList)
+{
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    if reader.read_line(&mut request_line).is_err()
+    {
+        return;
+    }
+
+    // Skip the request headers
+    let mut header = String::new();
+    while reader.read_line(&mut header).map(|n| n > 2).unwrap_or(false)
+    {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    let response = if method == "GET" && (path == "/metrics" || path.starts_with("/metrics?"))
+    {
+        let body = render_metrics(profile_monitors);
+        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+    else
+    {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+
+    let _ = stream.write_all(response.as_bytes());
+}
+
+// --------------------------------------------------------------------
+// render_metrics - Renders all metrics in the Prometheus text format
+// --------------------------------------------------------------------
+fn render_metrics(profile_monitors: &ProfileMonitorList) -> String
+{
+    let mut process_metrics: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+
+    let profile_monitors = profile_monitors.lock().unwrap().clone();
+    for profile_monitor in profile_monitors
+    {
+        let monitored_process_map = profile_monitor.monitored_process_map.lock().unwrap();
+        for (pid, entry) in monitored_process_map.iter()
+        {
+            if entry.threads.is_empty() || is_monitor_finished(entry)
+            {
+                continue;
+            }
+
+            let lock = entry.config.lock().unwrap();
+            let labels = format!("pid=\"{}\",process_name=\"{}\"", pid, escape_label(&lock.process_name));
+            let sample = &lock.last_sample;
+
+            if let Some(cpu_usage) = sample.cpu_usage
+            {
+                process_metrics.entry("procdump_process_cpu_percent").or_default().push(format!("{{{}}} {}", labels, cpu_usage));
+            }
+            if let Some(memory_usage) = sample.memory_usage
+            {
+                process_metrics.entry("procdump_process_memory_megabytes").or_default().push(format!("{{{}}} {}", labels, memory_usage));
+            }
+            if let Some(thread_count) = sample.thread_count
+            {
+                process_metrics.entry("procdump_process_threads").or_default().push(format!("{{{}}} {}", labels, thread_count));
+            }
+            if let Some(file_descriptor_count) = sample.file_descriptor_count
+            {
+                process_metrics.entry("procdump_process_file_descriptors").or_default().push(format!("{{{}}} {}", labels, file_descriptor_count));
+            }
+
+            // Thresholds, so dashboards can show how close a process is to a dump
+            let thresholds = [
+                ("cpu", lock.trigger_threshold_cpu),
+                ("memory", lock.trigger_threshold_mem),
+                ("threads", lock.trigger_threshold_threads),
+                ("file_descriptor", lock.trigger_threshold_file_descriptors),
+            ];
+            for (trigger, threshold) in thresholds
+            {
+                if threshold != u32::MAX
+                {
+                    process_metrics.entry("procdump_trigger_threshold").or_default().push(format!("{{{},trigger=\"{}\"}} {}", labels, trigger, threshold));
+                }
+            }
+
+            process_metrics.entry("procdump_process_dumps_collected").or_default().push(format!("{{{}}} {}", labels, lock.number_of_dumps_collected));
+        }
+    }
+
+    let mut output = String::new();
+    let help = [
+        ("procdump_process_cpu_percent", "CPU usage of the monitored process in percent."),
+        ("procdump_process_memory_megabytes", "Memory commit (resident + swap) of the monitored process in MB."),
+        ("procdump_process_threads", "Thread count of the monitored process."),
+        ("procdump_process_file_descriptors", "File descriptor count of the monitored process."),
+        ("procdump_trigger_threshold", "Threshold at which a dump of the monitored process is triggered."),
+        ("procdump_process_dumps_collected", "Number of dumps collected for the monitored process."),
+    ];
+    for (name, description) in help
+    {
+        let _ = writeln!(output, "# HELP {} {}", name, description);
+        let _ = writeln!(output, "# TYPE {} gauge", name);
+        for sample in process_metrics.get(name).into_iter().flatten()
+        {
+            let _ = writeln!(output, "{}{}", name, sample);
+        }
+    }
+
+    let counters = COUNTERS.lock().unwrap();
+    render_counter(&mut output, "procdump_triggers_fired_total", "Number of triggers fired.", &counters.triggers_fired);
+    render_counter(&mut output, "procdump_dumps_collected_total", "Number of dumps successfully written.", &counters.dumps_collected);
+    render_counter(&mut output, "procdump_dumps_failed_total", "Number of dumps that failed.", &counters.dumps_failed);
+
+    let _ = writeln!(output, "# HELP procdump_dump_duration_seconds Time taken to write a dump.");
+    let _ = writeln!(output, "# TYPE procdump_dump_duration_seconds histogram");
+    for (i, bound) in DUMP_DURATION_BUCKETS.iter().enumerate()
+    {
+        let _ = writeln!(output, "procdump_dump_duration_seconds_bucket{{le=\"{}\"}} {}", bound, counters.dump_duration_buckets[i]);
+    }
+    let _ = writeln!(output, "procdump_dump_duration_seconds_bucket{{le=\"+Inf\"}} {}", counters.dump_duration_count);
+    let _ = writeln!(output, "procdump_dump_duration_seconds_sum {}", counters.dump_duration_sum);
+    let _ = writeln!(output, "procdump_dump_duration_seconds_count {}", counters.dump_duration_count);
+
+    output
+}
+
+// --------------------------------------------------------------------
+// render_counter - Renders a counter labelled by trigger type
+// --------------------------------------------------------------------
+fn render_counter(output: &mut String, name: &str, description: &str, values: &BTreeMap<String, u64>)
+{
+    let _ = writeln!(output, "# HELP {} {}", name, description);
+    let _ = writeln!(output, "# TYPE {} counter", name);
+    for (trigger, value) in values
+    {
+        let _ = writeln!(output, "{}{{trigger=\"{}\"}} {}", name, escape_label(trigger), value);
+    }
+}
+
+// --------------------------------------------------------------------
+// escape_label - Escapes a label value
+// --------------------------------------------------------------------
+fn escape_label(value: &str) -> String
+{
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -7,12 +7,16 @@
 //
 //--------------------------------------------------------------------
 use crate::procdumpconfiguration::ProcDumpConfiguration;
-use crate::procdumpconfiguration::print_configuration;
+use crate::events::{Event, emit};
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::retune_configuration;
 use crate::processhelpers::*;
+use crate::procconnector::{ProcConnector, ProcEvent};
 use crate::triggerthreadprocs;
 use std::collections::HashMap;
 use std::{thread, time};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
 use std::fs;
 
@@ -20,18 +24,37 @@ pub struct MonitoredProcessMapEntry
 {
     pub active: bool,
     pub starttime: u64,
+    pub process_handle: Arc<ProcessHandle>,                 // Shared with the config so monitor threads and dumps can tell the target apart from a PID reuse
     pub config: Arc<Mutex<ProcDumpConfiguration>>,      // ProcDumpConfiguration is shared and hence protected by Mutex wrapped by an Arc for atomic reference couting
     pub threads: Vec<Option<JoinHandle<u32>>>,
 }
 
+//
+// A monitoring profile (command line or configuration file). The configuration is the
+// template for every process matching the profile and can be retuned while monitoring.
+//
+pub struct ProfileMonitor
+{
+    pub config: Arc<Mutex<ProcDumpConfiguration>>,
+    pub monitored_process_map: Arc<Mutex<HashMap<i32, MonitoredProcessMapEntry>>>,
+    pub stop_requested: AtomicBool,
+    pub thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+// All profiles being monitored, shared with the control socket
+pub type ProfileMonitorList = Arc<Mutex<Vec<Arc<ProfileMonitor>>>>;
+
+// Longest wait for process events before checking if the profile is being stopped
+const PROC_EVENT_WAIT_SLICE: time::Duration = time::Duration::from_millis(100);
+
 // -----------------------------------------------------------------
 // monitor_processes - Monitors all processes and creates monitors
 // based on the configuration
 // -----------------------------------------------------------------
-pub fn monitor_processes(config: &mut ProcDumpConfiguration)
+pub fn monitor_processes(profile: &ProfileMonitor)
 {
-    let mut monitored_process_map: HashMap<i32, MonitoredProcessMapEntry>;
-    monitored_process_map = HashMap::new();
+    let mut profile_config = profile.config.lock().unwrap().clone();
+    let config = &mut profile_config;
 
     if config.waiting_process_name
     {
@@ -75,40 +98,87 @@ pub fn monitor_processes(config: &mut ProcDumpConfiguration)
             return;
         }
 
-        let stat_path = format!("/proc/{}/stat", config.process_id);
-        let statcontents = fs::read_to_string(stat_path).expect("Stat file not found.");
-
-        config.process_start_time = statcontents.split(" ").nth(21).unwrap().parse::<u64>().unwrap();
+        config.process_start_time = get_process_start_time(config.process_id);
         config.process_name = get_process_name_by_pid(config.process_id);
 
-        let config_clone = config.clone();
-        let mut entry = MonitoredProcessMapEntry
+        // A profile restarted by a reload keeps the entry (and dump count) of its process
+        let carried_entry = profile.monitored_process_map.lock().unwrap().remove(&config.process_id);
+        let mut entry = match carried_entry
         {
-            active: false,
-            starttime: get_process_start_time(config_clone.process_id),
-            config: Arc::new(Mutex::new(config_clone)),
-            threads: Vec::new(),
+            Some(entry) if entry.starttime == config.process_start_time && !entry.process_handle.has_exited() => entry,
+            _ =>
+            {
+                let process_handle = Arc::new(ProcessHandle::open(config.process_id));
+                let mut config_clone = config.clone();
+                config_clone.process_handle = Some(process_handle.clone());
+                MonitoredProcessMapEntry
+                {
+                    active: false,
+                    starttime: process_handle.start_time,
+                    process_handle,
+                    config: Arc::new(Mutex::new(config_clone)),
+                    threads: Vec::new(),
+                }
+            },
         };
 
-        print_configuration(config);
+        emit(Event::Config { config });
         println!();
 
+        let dumps_collected = entry.config.lock().unwrap().number_of_dumps_collected;
+        if dumps_collected >= config.number_of_dumps_to_collect
+        {
+            println!("All {} dumps of process {} have already been collected.", config.number_of_dumps_to_collect, config.process_id);
+            profile.monitored_process_map.lock().unwrap().insert(config.process_id, entry);
+            return;
+        }
+
         if !start_monitor(&mut entry)
         {
             println!("Failed to start monitor for pid: {}", config.process_id);
             return;
         }
-        monitored_process_map.insert(config.process_id, entry);
+        profile.monitored_process_map.lock().unwrap().insert(config.process_id, entry);
 
+        // Wait for the monitors to exit or for the profile to be stopped
+        while !profile.stop_requested.load(Ordering::SeqCst)
+        {
+            {
+                let monitored_process_map = profile.monitored_process_map.lock().unwrap();
+                let entry_o = monitored_process_map.get(&config.process_id).unwrap();
+                if is_monitor_finished(entry_o)
+                {
+                    break;
+                }
+            }
+
+            thread::park_timeout(time::Duration::from_millis(config.polling_frequency));
+        }
+
+        let mut monitored_process_map = profile.monitored_process_map.lock().unwrap();
         let entry_o = monitored_process_map.get_mut(&config.process_id).unwrap();
 
-        wait_for_monitor_exit(entry_o);
-        println!("Stopping monitor for process {} ({})", config.process_name, config.process_id);
-        monitored_process_map.remove(&config.process_id);
+        stop_monitor(entry_o);
+        if !is_process_running(config.process_id)
+        {
+            emit(Event::ProcessExited { pid: config.process_id, process_name: &config.process_name });
+        }
+        emit(Event::MonitorStopped { pid: config.process_id, process_name: &config.process_name });
+        // The entry is kept so a restart of the profile knows the dumps already collected
+        if !is_process_running(config.process_id)
+        {
+            monitored_process_map.remove(&config.process_id);
+        }
+
+        // Stop processes added at runtime along with the profile
+        for (_pid, entry) in monitored_process_map.iter_mut()
+        {
+            stop_monitor(entry);
+        }
     }
     else
     {
-        print_configuration(config);
+        emit(Event::Config { config });
         println!();
 
         if config.is_process_group_set
@@ -122,10 +192,48 @@ pub fn monitor_processes(config: &mut ProcDumpConfiguration)
             println!();
         }
 
-        let mut num_monitored_process = 0;
+        // Discover new processes from the process connector if we are privileged,
+        // otherwise rescan /proc every polling interval
+        let proc_connector = match ProcConnector::open()
+        {
+            Ok(proc_connector) =>
+            {
+                log(Severity::Debug, "Discovering processes with the netlink process connector");
+                Some(proc_connector)
+            },
+            Err(e) =>
+            {
+                log(Severity::Debug, &format!("Netlink process connector unavailable ({}), scanning /proc instead", e));
+                None
+            },
+        };
+        let mut proc_events: Vec<ProcEvent> = Vec::new();
+        let mut rescan = true;
+
         loop
         {
             // Multi process monitoring
+            let mut monitored_process_map = profile.monitored_process_map.lock().unwrap();
+
+            // Stop all monitors if the profile is being stopped
+            if profile.stop_requested.load(Ordering::SeqCst)
+            {
+                for (pid, entry) in monitored_process_map.iter_mut()
+                {
+                    if entry.active
+                    {
+                        stop_monitor(entry);
+                        emit(Event::MonitorStopped { pid: *pid, process_name: &entry.config.lock().unwrap().process_name });
+                        entry.active = false;
+                    }
+                }
+
+                break;
+            }
+
+            // Pick up any settings retuned since the last iteration
+            profile_config = profile.config.lock().unwrap().clone();
+            let config = &mut profile_config;
 
             // Get PGID of process
             let pgid = get_process_pgid(config.process_pgid);
@@ -136,126 +244,49 @@ pub fn monitor_processes(config: &mut ProcDumpConfiguration)
                 return;
             }
 
-            // Iterate over all running processes
-            for entry in fs::read_dir("/proc/").expect("I told you this directory exists")
+            if rescan
             {
-                let entry = entry.expect("I couldn't read something inside the directory");
-                let path = entry.path();
-                let pid = path.file_name().unwrap().to_str().unwrap().to_lowercase();
-                let proc_pid = match pid.parse::<i32>()
-                {
-                    Ok(pid) => pid,
-                    Err(_err) => { continue; },
-                };
-
-                if config.is_process_group_set
+                // Iterate over all running processes
+                for entry in fs::read_dir("/proc/").expect("I told you this directory exists")
                 {
-                    // We're monitoring a process group (-pgid)
-                    let pgid = get_process_pgid(proc_pid);
-                    if pgid != u64::MAX && config.process_pgid as u64 == pgid
+                    let entry = entry.expect("I couldn't read something inside the directory");
+                    let path = entry.path();
+                    let pid = path.file_name().unwrap().to_str().unwrap().to_lowercase();
+                    let proc_pid = match pid.parse::<i32>()
                     {
-                        let start_time = get_process_start_time(proc_pid);
+                        Ok(pid) => pid,
+                        Err(_err) => { continue; },
+                    };
 
-                        if !monitored_process_map.contains_key(&proc_pid)
-                        {
-                            // New process, setup new monitor
-                            let mut entry = get_new_process_map_entry(config, proc_pid);
-                            if !start_monitor(&mut entry)
-                            {
-                                println!("Failed to start monitor for pid: {}", config.process_id);
-                            }
-
-                            monitored_process_map.insert(proc_pid, entry);
-                            num_monitored_process += 1;
-                        }
-                        else
-                        {
-                            // We've already seen this process...
-                            // If the active flag = true, its an active monitor
-                            // If the active flag = false, check to see if starttimes are different...
-                            // if they are, we have a case of PID reuse (highly unlikely)
-                            let entry = monitored_process_map.get(&proc_pid).unwrap();
-                            if entry.active == false && entry.starttime != start_time
-                            {
-                                // PID reuse
-
-                                // First remove existing entry since we have to setup a new monitor (monitoring threads etc)
-                                let lock = entry.config.lock().unwrap();
-                                let pid = lock.process_id;
-                                drop(lock);
-
-                                monitored_process_map.remove(&pid);
-                                num_monitored_process -= 1;
-
-                                let mut entry = get_new_process_map_entry(config, proc_pid);
-                                if !start_monitor(&mut entry)
-                                {
-                                    println!("Failed to start monitor for pid: {}", config.process_id);
-                                }
-
-                                monitored_process_map.insert(proc_pid, entry);
-                                num_monitored_process += 1;
-                            }
-                        }
-                    }
+                    discover_process(config, &mut monitored_process_map, proc_pid);
                 }
-                else if config.waiting_process_name
+            }
+            else
+            {
+                // Only look at the processes reported by the process connector since the last iteration
+                for event in &proc_events
                 {
-                    // We are monitoring for a process name (-w)
-                    let name_for_pid = get_process_name_by_pid(proc_pid);
-
-                    if !name_for_pid.is_empty() && name_for_pid.eq(&config.process_name)
+                    match *event
                     {
-                        let start_time = get_process_start_time(proc_pid);
-
-                        if !monitored_process_map.contains_key(&proc_pid)
+                        ProcEvent::Fork(proc_pid) | ProcEvent::Exec(proc_pid) => discover_process(config, &mut monitored_process_map, proc_pid),
+                        ProcEvent::Exit(proc_pid) =>
                         {
-                            // New process, setup new monitor
-                            let mut entry = get_new_process_map_entry(config, proc_pid);
-                            if !start_monitor(&mut entry)
+                            // Don't wait for the monitor threads to notice the process is gone
+                            if let Some(entry) = monitored_process_map.get_mut(&proc_pid)
                             {
-                                println!("Failed to start monitor for pid: {}", config.process_id);
-                            }
-
-                            monitored_process_map.insert(proc_pid, entry);
-                            num_monitored_process += 1;
-                        }
-                        else
-                        {
-                            // We've already seen this process...
-                            // If the active flag = true, its an active monitor
-                            // If the active flag = false, check to see if starttimes are different...
-                            // if they are, we have a case of PID reuse (highly unlikely)
-                            let entry = monitored_process_map.get(&proc_pid).unwrap();
-                            if entry.active == false && entry.starttime != start_time
-                            {
-                                // PID reuse
-
-                                // First remove existing entry since we have to setup a new monitor (monitoring threads etc)
-                                let lock = entry.config.lock().unwrap();
-                                let pid = lock.process_id;
-                                drop(lock);
-
-                                monitored_process_map.remove(&pid);
-                                num_monitored_process -= 1;
-
-                                let mut entry = get_new_process_map_entry(config, proc_pid);
-                                if !start_monitor(&mut entry)
+                                if entry.active && entry.process_handle.has_exited()
                                 {
-                                    println!("Failed to start monitor for pid: {}", config.process_id);
+                                    stop_monitor(entry);
                                 }
-
-                                monitored_process_map.insert(proc_pid, entry);
-                                num_monitored_process += 1;
                             }
-                        }
+                        },
                     }
                 }
             }
 
             // Iterate over the list of monitored processes and stash the ones which we should stop monitoring
             let mut del_items: Vec<i32> = Vec::new();
-            for (_pid, entry) in &mut monitored_process_map
+            for (_pid, entry) in monitored_process_map.iter_mut()
             {
                 if entry.active
                 {
@@ -271,105 +302,413 @@ pub fn monitor_processes(config: &mut ProcDumpConfiguration)
             for item in &del_items
             {
                 let entry_o = monitored_process_map.get_mut(&item).unwrap();
-                println!("Stopping monitors for process: {}", item);
                 wait_for_monitor_exit(entry_o);
                 entry_o.active = false;
-                num_monitored_process -= 1;
+
+                let lock = entry_o.config.lock().unwrap();
+                let process_name = lock.process_name.clone();
+                let terminated = lock.process_terminated || entry_o.process_handle.has_exited() || !is_process_running(*item);
+                drop(lock);
+
+                if terminated
+                {
+                    emit(Event::ProcessExited { pid: *item, process_name: &process_name });
+                }
+                emit(Event::MonitorStopped { pid: *item, process_name: &process_name });
+
+                // The process is gone, if its PID shows up again it's a new process
+                if terminated
+                {
+                    monitored_process_map.remove(item);
+                }
             }
 
             // Exit if we are monitoring PGID and there are no more processes to monitor.
             // If we are monitoring for processes based on a process name we keep monitoring
+            let num_monitored_process = monitored_process_map.values().filter(|entry| entry.active).count();
             if num_monitored_process == 0 && config.waiting_process_name == false
             {
                 break;
             }
 
-            thread::sleep(time::Duration::from_millis(config.polling_frequency));
+            let polling_frequency = config.polling_frequency;
+            drop(monitored_process_map);
+
+            match &proc_connector
+            {
+                Some(proc_connector) =>
+                {
+                    // Rescan /proc if events were lost
+                    match wait_for_proc_events(profile, proc_connector, polling_frequency)
+                    {
+                        Some(events) =>
+                        {
+                            proc_events = events;
+                            rescan = false;
+                        },
+                        None => rescan = true,
+                    }
+                },
+                None => thread::park_timeout(time::Duration::from_millis(polling_frequency)),
+            }
         }
     }
 }
 
+// -----------------------------------------------------------------
+// discover_process - Starts monitoring the process if it matches the
+// process name (-w) or process group (-pgid) of the profile
+// -----------------------------------------------------------------
+fn discover_process(config: &mut ProcDumpConfiguration, monitored_process_map: &mut HashMap<i32, MonitoredProcessMapEntry>, proc_pid: i32)
+{
+    if config.is_process_group_set
+    {
+        // We're monitoring a process group (-pgid)
+        let pgid = get_process_pgid(proc_pid);
+        if pgid != u64::MAX && config.process_pgid as u64 == pgid
+        {
+            let start_time = get_process_start_time(proc_pid);
+
+            if !monitored_process_map.contains_key(&proc_pid)
+            {
+                // New process, setup new monitor
+                let mut entry = get_new_process_map_entry(config, proc_pid);
+                if !start_monitor(&mut entry)
+                {
+                    println!("Failed to start monitor for pid: {}", config.process_id);
+                }
+
+                monitored_process_map.insert(proc_pid, entry);
+            }
+            else
+            {
+                // We've already seen this process...
+                // If the active flag = true, its an active monitor
+                // If the active flag = false, check to see if the process we monitored has exited...
+                // if it has, we have a case of PID reuse (highly unlikely)
+                let entry = monitored_process_map.get(&proc_pid).unwrap();
+                if !entry.active && (entry.process_handle.has_exited() || entry.starttime != start_time)
+                {
+                    // PID reuse
+
+                    // First remove existing entry since we have to setup a new monitor (monitoring threads etc)
+                    let lock = entry.config.lock().unwrap();
+                    let pid = lock.process_id;
+                    drop(lock);
+
+                    monitored_process_map.remove(&pid);
+
+                    let mut entry = get_new_process_map_entry(config, proc_pid);
+                    if !start_monitor(&mut entry)
+                    {
+                        println!("Failed to start monitor for pid: {}", config.process_id);
+                    }
+
+                    monitored_process_map.insert(proc_pid, entry);
+                }
+            }
+        }
+    }
+    else if config.waiting_process_name
+    {
+        // We are monitoring for a process name (-w)
+        let name_for_pid = get_process_name_by_pid(proc_pid);
+
+        if !name_for_pid.is_empty() && name_for_pid.eq(&config.process_name)
+        {
+            let start_time = get_process_start_time(proc_pid);
+
+            if !monitored_process_map.contains_key(&proc_pid)
+            {
+                // New process, setup new monitor
+                let mut entry = get_new_process_map_entry(config, proc_pid);
+                if !start_monitor(&mut entry)
+                {
+                    println!("Failed to start monitor for pid: {}", config.process_id);
+                }
+
+                monitored_process_map.insert(proc_pid, entry);
+            }
+            else
+            {
+                // We've already seen this process...
+                // If the active flag = true, its an active monitor
+                // If the active flag = false, check to see if the process we monitored has exited...
+                // if it has, we have a case of PID reuse (highly unlikely)
+                let entry = monitored_process_map.get(&proc_pid).unwrap();
+                if !entry.active && (entry.process_handle.has_exited() || entry.starttime != start_time)
+                {
+                    // PID reuse
+
+                    // First remove existing entry since we have to setup a new monitor (monitoring threads etc)
+                    let lock = entry.config.lock().unwrap();
+                    let pid = lock.process_id;
+                    drop(lock);
+
+                    monitored_process_map.remove(&pid);
+
+                    let mut entry = get_new_process_map_entry(config, proc_pid);
+                    if !start_monitor(&mut entry)
+                    {
+                        println!("Failed to start monitor for pid: {}", config.process_id);
+                    }
+
+                    monitored_process_map.insert(proc_pid, entry);
+                }
+            }
+        }
+    }
+}
 
 // -----------------------------------------------------------------
-// start_monitor - Starts a monitor based on the configuration
+// wait_for_proc_events - Waits up to the polling interval for process
+// events, returning early as soon as one is received. Returns None if
+// events were lost and /proc must be rescanned.
 // -----------------------------------------------------------------
-pub fn start_monitor(entry: &mut MonitoredProcessMapEntry) -> bool
+fn wait_for_proc_events(profile: &ProfileMonitor, proc_connector: &ProcConnector, polling_frequency: u64) -> Option<Vec<ProcEvent>>
 {
-    let guard = entry.config.lock().unwrap();
-    if guard.trigger_threshold_mem != u32::MAX
+    let deadline = time::Instant::now() + time::Duration::from_millis(polling_frequency);
+    let mut events = Vec::new();
+
+    // Wait in slices so that stopping the profile isn't delayed
+    while events.is_empty() && !profile.stop_requested.load(Ordering::SeqCst)
     {
-        let config_clone = entry.config.clone();
+        let remaining = deadline.saturating_duration_since(time::Instant::now());
+        if remaining.is_zero()
+        {
+            break;
+        }
 
-        let thread = thread::Builder::new().name("Memory monitor thread".to_string()).spawn(move || triggerthreadprocs::mem_monitoring_thread(config_clone));
-        if thread.is_err()
+        match proc_connector.wait_for_events(remaining.min(PROC_EVENT_WAIT_SLICE))
         {
-            return false;
+            Ok(received) => events = received,
+            Err(e) =>
+            {
+                log(Severity::Warning, &format!("Failed to read process events, rescanning /proc: {}", e));
+                return None;
+            },
         }
+    }
 
-        entry.threads.push(Some(thread.unwrap()));
+    Some(events)
+}
+
+
+// -----------------------------------------------------------------
+// monitor_profiles - Monitors each profile on its own thread until
+// all of them exit
+// -----------------------------------------------------------------
+pub fn monitor_profiles(profiles: Vec<ProcDumpConfiguration>, profile_monitors: &ProfileMonitorList)
+{
+    for profile in profiles
+    {
+        if let Some(profile_monitor) = start_profile_monitor(profile)
+        {
+            profile_monitors.lock().unwrap().push(profile_monitor);
+        }
     }
 
-    if guard.trigger_threshold_cpu != u32::MAX
+    let started = profile_monitors.lock().unwrap().clone();
+    for profile_monitor in &started
     {
-        let config_clone = entry.config.clone();
+        wait_for_profile_monitor_exit(profile_monitor);
+    }
+}
 
-        let thread = thread::Builder::new().name("CPU monitor thread".to_string()).spawn(move || triggerthreadprocs::cpu_monitoring_thread(config_clone));
-        if thread.is_err()
+// -----------------------------------------------------------------
+// start_profile_monitor - Starts monitoring a profile on a new thread
+// -----------------------------------------------------------------
+pub fn start_profile_monitor(config: ProcDumpConfiguration) -> Option<Arc<ProfileMonitor>>
+{
+    spawn_profile_monitor(config, Arc::new(Mutex::new(HashMap::new())))
+}
+
+// -----------------------------------------------------------------
+// restart_profile_monitor - Starts a profile that has run to completion
+// over with new settings. The process map is carried over so processes
+// keep their dump counts and aren't dumped again.
+// -----------------------------------------------------------------
+pub fn restart_profile_monitor(profile: &ProfileMonitor, config: ProcDumpConfiguration) -> Option<Arc<ProfileMonitor>>
+{
+    wait_for_profile_monitor_exit(profile);
+
+    for entry in profile.monitored_process_map.lock().unwrap().values_mut()
+    {
+        retune_configuration(&mut entry.config.lock().unwrap(), &config);
+    }
+
+    spawn_profile_monitor(config, profile.monitored_process_map.clone())
+}
+
+// -----------------------------------------------------------------
+// spawn_profile_monitor - Creates the profile monitor and its thread
+// -----------------------------------------------------------------
+fn spawn_profile_monitor(config: ProcDumpConfiguration, monitored_process_map: Arc<Mutex<HashMap<i32, MonitoredProcessMapEntry>>>) -> Option<Arc<ProfileMonitor>>
+{
+    let profile_name = config.profile_name.clone();
+    if !profile_name.is_empty()
+    {
+        log(Severity::Info, &format!("Starting profile '{}'", profile_name));
+    }
+    let profile_monitor = Arc::new(ProfileMonitor
+    {
+        config: Arc::new(Mutex::new(config)),
+        monitored_process_map,
+        stop_requested: AtomicBool::new(false),
+        thread: Mutex::new(None),
+    });
+
+    let profile_clone = profile_monitor.clone();
+    let thread = thread::Builder::new().name(format!("Profile monitor thread {}", profile_name)).spawn(move || monitor_processes(&profile_clone));
+    match thread
+    {
+        Ok(thread) =>
         {
-            return false;
-        }
+            *profile_monitor.thread.lock().unwrap() = Some(thread);
+            Some(profile_monitor)
+        },
+        Err(_) =>
+        {
+            println!("Failed to start monitoring for profile: {}", profile_name);
+            log(Severity::Error, &format!("Failed to create thread for profile '{}'", profile_name));
+            None
+        },
+    }
+}
 
-        entry.threads.push(Some(thread.unwrap()));
+// -----------------------------------------------------------------
+// stop_profile_monitor - Stops all monitors of a profile and waits
+// for the profile thread to exit
+// -----------------------------------------------------------------
+pub fn stop_profile_monitor(profile: &ProfileMonitor)
+{
+    log(Severity::Info, &format!("Stopping profile '{}'", profile.config.lock().unwrap().profile_name));
+    profile.stop_requested.store(true, Ordering::SeqCst);
+    if let Some(thread) = profile.thread.lock().unwrap().as_ref()
+    {
+        thread.thread().unpark();
     }
 
-    if guard.trigger_threshold_threads != u32::MAX
+    wait_for_profile_monitor_exit(profile);
+}
+
+// -----------------------------------------------------------------
+// wait_for_profile_monitor_exit - Waits for a profile thread to exit
+// -----------------------------------------------------------------
+pub fn wait_for_profile_monitor_exit(profile: &ProfileMonitor)
+{
+    let thread = profile.thread.lock().unwrap().take();
+    if let Some(thread) = thread
     {
-        let config_clone = entry.config.clone();
+        thread.join().expect("Failed to join profile monitor thread");
+    }
+}
 
-        let thread = thread::Builder::new().name("Thread monitor thread".to_string()).spawn(move || triggerthreadprocs::thread_monitoring_thread(config_clone));
-        if thread.is_err()
+// -----------------------------------------------------------------
+// is_profile_monitor_running - returns true if the profile thread is
+// still monitoring, false otherwise
+// -----------------------------------------------------------------
+pub fn is_profile_monitor_running(profile: &ProfileMonitor) -> bool
+{
+    match profile.thread.lock().unwrap().as_ref()
+    {
+        Some(thread) => !thread.is_finished(),
+        None => false,
+    }
+}
+
+// -----------------------------------------------------------------
+// retune_profile_monitor - Applies new settings to a running profile.
+// Monitors of the profile are restarted with the new settings while
+// keeping their process map entries (dump counts, start times).
+// -----------------------------------------------------------------
+pub fn retune_profile_monitor(profile: &ProfileMonitor, config: &ProcDumpConfiguration)
+{
+    log(Severity::Info, &format!("Retuning profile '{}'", config.profile_name));
+    *profile.config.lock().unwrap() = config.clone();
+
+    let mut monitored_process_map = profile.monitored_process_map.lock().unwrap();
+    for (pid, entry) in monitored_process_map.iter_mut()
+    {
+        let restart = !is_monitor_finished(entry) && !entry.config.lock().unwrap().is_quit;
+        if restart
         {
-            return false;
+            stop_monitor(entry);
         }
 
-        entry.threads.push(Some(thread.unwrap()));
+        retune_configuration(&mut entry.config.lock().unwrap(), config);
 
+        if restart && !start_monitor(entry)
+        {
+            println!("Failed to restart monitor for pid: {}", pid);
+            log(Severity::Error, &format!("Failed to restart monitor for process {}", pid));
+        }
     }
+}
 
-    if guard.trigger_threshold_file_descriptors != u32::MAX
+// -----------------------------------------------------------------
+// dump_monitored_processes - Writes a manual dump of every process
+// with an active monitor. Dumps are queued to the dump workers.
+// -----------------------------------------------------------------
+pub fn dump_monitored_processes(profile_monitors: &ProfileMonitorList)
+{
+    let mut configs = Vec::new();
+    for profile_monitor in profile_monitors.lock().unwrap().iter()
     {
-        let config_clone = entry.config.clone();
-
-        let thread = thread::Builder::new().name("File monitor thread".to_string()).spawn(move || triggerthreadprocs::file_monitoring_thread(config_clone));
-        if thread.is_err()
+        for (_pid, entry) in profile_monitor.monitored_process_map.lock().unwrap().iter()
         {
-            return false;
+            if !entry.threads.is_empty() && !is_monitor_finished(entry)
+            {
+                configs.push(entry.config.clone());
+            }
         }
+    }
 
-        entry.threads.push(Some(thread.unwrap()));
+    for config in configs
+    {
+        let _ = thread::Builder::new().name("Manual dump thread".to_string()).spawn(move ||
+        {
+            if let Err(e) = triggerthreadprocs::manual_dump(&config)
+            {
+                println!("{}", e);
+            }
+        });
     }
+}
 
-    if guard.trigger_signal != u32::MAX
+// -----------------------------------------------------------------
+// start_monitor - Starts a monitor based on the configuration
+// -----------------------------------------------------------------
+pub fn start_monitor(entry: &mut MonitoredProcessMapEntry) -> bool
+{
+    let mut guard = entry.config.lock().unwrap();
+    guard.monitor_cancelled = false;
+    guard.is_quit = false;
+
+    // One sampler evaluates all the sampled triggers of the process
+    if guard.trigger_threshold_mem != u32::MAX || guard.trigger_threshold_cpu != u32::MAX || guard.trigger_threshold_threads != u32::MAX ||
+       guard.trigger_threshold_file_descriptors != u32::MAX || guard.trigger_threshold_timer
     {
         let config_clone = entry.config.clone();
 
-        let thread = thread::Builder::new().name("Signal monitor thread".to_string()).spawn(move || triggerthreadprocs::signal_monitoring_thread(config_clone));
+        let thread = thread::Builder::new().name("Sampler thread".to_string()).spawn(move || triggerthreadprocs::sampler_thread(config_clone));
         if thread.is_err()
         {
+            log(Severity::Error, &format!("Failed to create monitor thread for process {}", guard.process_id));
             return false;
         }
 
         entry.threads.push(Some(thread.unwrap()));
-
     }
 
-    if guard.trigger_threshold_timer
+    if guard.trigger_signal != u32::MAX
     {
         let config_clone = entry.config.clone();
 
-        let thread = thread::Builder::new().name("Timer monitor thread".to_string()).spawn(move || triggerthreadprocs::timer_monitoring_thread(config_clone));
+        let thread = thread::Builder::new().name("Signal monitor thread".to_string()).spawn(move || triggerthreadprocs::signal_monitoring_thread(config_clone));
         if thread.is_err()
         {
+            log(Severity::Error, &format!("Failed to create monitor thread for process {}", guard.process_id));
             return false;
         }
 
@@ -377,7 +716,7 @@ pub fn start_monitor(entry: &mut MonitoredProcessMapEntry) -> bool
 
     }
 
-    println!("Starting monitor for process {} ({})", guard.process_name, guard.process_id);
+    emit(Event::MonitorStarted { pid: guard.process_id, process_name: &guard.process_name });
 
     true
 }
@@ -389,13 +728,47 @@ pub fn wait_for_monitor_exit(entry: &mut MonitoredProcessMapEntry) -> bool
 {
     for i in 0..entry.threads.len()
     {
-        let join_handle = std::mem::take(&mut entry.threads[i]);
-        join_handle.unwrap().join().expect("Failed to join monitor thread");
+        if let Some(join_handle) = std::mem::take(&mut entry.threads[i])
+        {
+            if join_handle.join().is_err()
+            {
+                log(Severity::Error, &format!("Monitor thread of process {} panicked", entry.config.lock().unwrap().process_id));
+            }
+        }
     }
 
+    entry.threads.clear();
+
     true
 }
 
+// -----------------------------------------------------------------
+// stop_monitor - Cancels the monitor threads of a process and waits
+// for them to exit
+// -----------------------------------------------------------------
+pub fn stop_monitor(entry: &mut MonitoredProcessMapEntry) -> bool
+{
+    log(Severity::Debug, &format!("Cancelling monitor threads of process {}", entry.config.lock().unwrap().process_id));
+
+    // Wake up the monitor threads so they see the cancellation
+    entry.config.lock().unwrap().monitor_cancelled = true;
+    for join_handle in entry.threads.iter().flatten()
+    {
+        join_handle.thread().unpark();
+    }
+
+    wait_for_monitor_exit(entry)
+}
+
+// -----------------------------------------------------------------
+// is_monitor_finished - returns true if all monitor threads of a
+// process have exited, false otherwise
+// -----------------------------------------------------------------
+pub fn is_monitor_finished(entry: &MonitoredProcessMapEntry) -> bool
+{
+    entry.threads.iter().flatten().all(|join_handle| join_handle.is_finished())
+}
+
 // -----------------------------------------------------------------
 // get_new_process_map_entry - Gets a new process map entry based
 // on config specified. It clones the config and updates the pid
@@ -403,13 +776,16 @@ pub fn wait_for_monitor_exit(entry: &mut MonitoredProcessMapEntry) -> bool
 // -----------------------------------------------------------------
 pub fn get_new_process_map_entry(config: &mut ProcDumpConfiguration, proc_pid: i32) -> MonitoredProcessMapEntry
 {
+    let process_handle = Arc::new(ProcessHandle::open(proc_pid));
     let mut config_clone = config.clone();
     config_clone.process_id = proc_pid;
     config_clone.process_name = get_process_name_by_pid(proc_pid);
+    config_clone.process_handle = Some(process_handle.clone());
     let entry = MonitoredProcessMapEntry
     {
         active: true,
-        starttime: get_process_start_time(config_clone.process_id),
+        starttime: process_handle.start_time,
+        process_handle,
         config: Arc::new(Mutex::new(config_clone)),
         threads: Vec::new(),
     };
@@ -0,0 +1,302 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Netlink process connector - reports the processes forked, exec'ed and
+// exiting on the system so -w and -pgid can start monitors without
+// rescanning /proc. Listening requires CAP_NET_ADMIN before Linux 6.6
+// and is only possible in the initial network namespace.
+//
+//--------------------------------------------------------------------
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::sys::socket::{MsgFlags, NetlinkAddr, bind, recv, send};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+// <linux/connector.h> and <linux/cn_proc.h>
+const NETLINK_CONNECTOR: libc::c_int = 11;
+const CN_IDX_PROC: u32 = 1;
+const CN_VAL_PROC: u32 = 1;
+const PROC_CN_MCAST_LISTEN: u32 = 1;
+const PROC_CN_MCAST_IGNORE: u32 = 2;
+
+const PROC_EVENT_NONE: u32 = 0x00000000;
+const PROC_EVENT_FORK: u32 = 0x00000001;
+const PROC_EVENT_EXEC: u32 = 0x00000002;
+const PROC_EVENT_EXIT: u32 = 0x80000000;
+
+const NLMSG_HEADER_SIZE: usize = 16;
+const CN_MSG_HEADER_SIZE: usize = 20;
+const PROC_EVENT_HEADER_SIZE: usize = 16;
+const RECEIVE_BUFFER_SIZE: usize = 8192;
+
+// How long to wait for the kernel to acknowledge the subscription
+const LISTEN_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcEvent
+{
+    Fork(i32),          // PID of the new process
+    Exec(i32),
+    Exit(i32),
+}
+
+pub struct ProcConnector
+{
+    socket: OwnedFd,
+}
+
+impl ProcConnector
+{
+    // Subscribes to the process events, fails if procdump is not allowed to listen
+    pub fn open() -> nix::Result<ProcConnector>
+    {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, NETLINK_CONNECTOR) };
+        let socket = unsafe { OwnedFd::from_raw_fd(Errno::result(fd)?) };
+        bind(socket.as_raw_fd(), &NetlinkAddr::new(0, CN_IDX_PROC))?;
+        send(socket.as_raw_fd(), &get_control_message(PROC_CN_MCAST_LISTEN), MsgFlags::empty())?;
+
+        // The kernel acknowledges the subscription with the result of the request
+        let connector = ProcConnector { socket };
+        let deadline = Instant::now() + LISTEN_ACK_TIMEOUT;
+        let mut buffer = [0u8; RECEIVE_BUFFER_SIZE];
+        loop
+        {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !connector.wait_readable(remaining)?
+            {
+                return Err(Errno::ETIMEDOUT);
+            }
+
+            let size = recv(connector.socket.as_raw_fd(), &mut buffer, MsgFlags::MSG_DONTWAIT)?;
+            if let Some(result) = parse_listen_ack(&buffer[..size])
+            {
+                return result.map(|_| connector);
+            }
+        }
+    }
+
+    // --------------------------------------------------------------------
+    // wait_for_events - Waits up to the timeout for process events and
+    // returns all of those pending. Fails with ENOBUFS if events were
+    // dropped because they were not read fast enough.
+    // --------------------------------------------------------------------
+    pub fn wait_for_events(&self, timeout: Duration) -> nix::Result<Vec<ProcEvent>>
+    {
+        let mut events = Vec::new();
+        if !self.wait_readable(timeout)?
+        {
+            return Ok(events);
+        }
+
+        let mut buffer = [0u8; RECEIVE_BUFFER_SIZE];
+        loop
+        {
+            let size = match recv(self.socket.as_raw_fd(), &mut buffer, MsgFlags::MSG_DONTWAIT)
+            {
+                Ok(size) => size,
+                Err(Errno::EAGAIN) => return Ok(events),
+                Err(e) => return Err(e),
+            };
+
+            events.extend(parse_proc_events(&buffer[..size]));
+        }
+    }
+
+    fn wait_readable(&self, timeout: Duration) -> nix::Result<bool>
+    {
+        let mut fds = [PollFd::new(self.socket.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, timeout.as_millis().min(i32::MAX as u128) as i32)
+        {
+            Ok(ready) => Ok(ready > 0),
+            Err(Errno::EINTR) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Unsubscribe, before Linux 6.6 the kernel counts listeners until they do
+impl Drop for ProcConnector
+{
+    fn drop(&mut self)
+    {
+        let _ = send(self.socket.as_raw_fd(), &get_control_message(PROC_CN_MCAST_IGNORE), MsgFlags::empty());
+    }
+}
+
+// --------------------------------------------------------------------
+// get_control_message - Returns the netlink message subscribing to
+// (PROC_CN_MCAST_LISTEN) or unsubscribing from the process events
+// --------------------------------------------------------------------
+fn get_control_message(operation: u32) -> Vec<u8>
+{
+    let mut message = Vec::with_capacity(NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE + 4);
+    message.extend_from_slice(&((NLMSG_HEADER_SIZE + CN_MSG_HEADER_SIZE + 4) as u32).to_ne_bytes());
+    message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+    message.extend_from_slice(&0u16.to_ne_bytes());                     // flags
+    message.extend_from_slice(&0u32.to_ne_bytes());                     // sequence
+    message.extend_from_slice(&std::process::id().to_ne_bytes());
+    message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+    message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());                     // sequence
+    message.extend_from_slice(&0u32.to_ne_bytes());                     // ack
+    message.extend_from_slice(&4u16.to_ne_bytes());                     // payload length
+    message.extend_from_slice(&0u16.to_ne_bytes());                     // flags
+    message.extend_from_slice(&operation.to_ne_bytes());
+
+    message
+}
+
+// --------------------------------------------------------------------
+// parse_listen_ack - Returns the result of the subscription if the
+// datagram is its acknowledgement, None otherwise
+// --------------------------------------------------------------------
+fn parse_listen_ack(buffer: &[u8]) -> Option<nix::Result<()>>
+{
+    let (_, data) = parse_events(buffer).into_iter().find(|(what, _)| *what == PROC_EVENT_NONE)?;
+    match read_u32(data, 0)
+    {
+        Some(0) => Some(Ok(())),
+        Some(err) => Some(Err(Errno::from_i32(err as i32))),
+        None => Some(Err(Errno::EINVAL)),
+    }
+}
+
+// --------------------------------------------------------------------
+// parse_proc_events - Returns the fork, exec and exit events of the
+// processes (not threads) in a netlink datagram
+// --------------------------------------------------------------------
+fn parse_proc_events(buffer: &[u8]) -> Vec<ProcEvent>
+{
+    let mut events = Vec::new();
+    for (what, data) in parse_events(buffer)
+    {
+        // Only report processes, not threads (PID == TGID)
+        let event = match what
+        {
+            PROC_EVENT_FORK if read_u32(data, 8) == read_u32(data, 12) => read_u32(data, 12).map(|pid| ProcEvent::Fork(pid as i32)),
+            PROC_EVENT_EXEC if read_u32(data, 0) == read_u32(data, 4) => read_u32(data, 4).map(|pid| ProcEvent::Exec(pid as i32)),
+            PROC_EVENT_EXIT if read_u32(data, 0) == read_u32(data, 4) => read_u32(data, 4).map(|pid| ProcEvent::Exit(pid as i32)),
+            _ => None,
+        };
+
+        events.extend(event);
+    }
+
+    events
+}
+
+// --------------------------------------------------------------------
+// parse_events - Returns the type and data of each process event in a
+// netlink datagram
+// --------------------------------------------------------------------
+fn parse_events(buffer: &[u8]) -> Vec<(u32, &[u8])>
+{
+    let mut events = Vec::new();
+    let mut offset = 0;
+    while offset + NLMSG_HEADER_SIZE <= buffer.len()
+    {
+        let length = read_u32(buffer, offset).unwrap_or(0) as usize;
+        if length < NLMSG_HEADER_SIZE || offset + length > buffer.len()
+        {
+            break;
+        }
+
+        let message = &buffer[offset + NLMSG_HEADER_SIZE..offset + length];
+        if read_u32(message, 0) == Some(CN_IDX_PROC) && read_u32(message, 4) == Some(CN_VAL_PROC) && message.len() >= CN_MSG_HEADER_SIZE + PROC_EVENT_HEADER_SIZE
+        {
+            let event = &message[CN_MSG_HEADER_SIZE..];
+            if let Some(what) = read_u32(event, 0)
+            {
+                events.push((what, &event[PROC_EVENT_HEADER_SIZE..]));
+            }
+        }
+
+        // Messages are aligned on 4 bytes
+        offset += (length + 3) & !3;
+    }
+
+    events
+}
+
+fn read_u32(buffer: &[u8], offset: usize) -> Option<u32>
+{
+    buffer.get(offset..offset + 4).map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Datagrams captured on x86_64 while /bin/true (PID 14403) was run by PID 14362
+    const LISTEN_ACK: &str = "4c00000003000000203c0000000000000100000001000000203c0000010000002800000000000000000000002c5eb2e060020000000000000000000000000000000000000000000000000000";
+    const FORK: &str = "4c00000003000000213c0000000000000100000001000000213c000000000000280000000100000000000000a1b3b6e0600200001a3800001a38000043380000433800000000000000000000";
+    const EXEC: &str = "4c00000003000000223c0000000000000100000001000000223c000000000000280000000200000000000000fbf3bfe060020000433800004338000000000000000000000000000000000000";
+    const EXIT: &str = "4c00000003000000233c0000000000000100000001000000233c000000000000280000000000008000000000a31bc9e060020000433800004338000000000000110000001a3800001a380000";
+
+    fn datagram(hex: &str) -> Vec<u8>
+    {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_listen_ack()
+    {
+        assert_eq!(parse_listen_ack(&datagram(LISTEN_ACK)), Some(Ok(())));
+        assert_eq!(parse_listen_ack(&datagram(FORK)), None);
+
+        // An error is returned in place of 0
+        let mut refused = datagram(LISTEN_ACK);
+        refused[52..56].copy_from_slice(&(Errno::EPERM as u32).to_ne_bytes());
+        assert_eq!(parse_listen_ack(&refused), Some(Err(Errno::EPERM)));
+    }
+
+    #[test]
+    fn parses_process_events()
+    {
+        assert_eq!(parse_proc_events(&datagram(FORK)), vec![ProcEvent::Fork(14403)]);
+        assert_eq!(parse_proc_events(&datagram(EXEC)), vec![ProcEvent::Exec(14403)]);
+        assert_eq!(parse_proc_events(&datagram(EXIT)), vec![ProcEvent::Exit(14403)]);
+        assert_eq!(parse_proc_events(&datagram(LISTEN_ACK)), vec![]);
+    }
+
+    #[test]
+    fn ignores_threads()
+    {
+        // A thread is created with its own PID in the TGID of its process
+        let mut fork = datagram(FORK);
+        fork[60..64].copy_from_slice(&14404u32.to_ne_bytes());
+        assert_eq!(parse_proc_events(&fork), vec![]);
+
+        let mut exit = datagram(EXIT);
+        exit[52..56].copy_from_slice(&14404u32.to_ne_bytes());
+        assert_eq!(parse_proc_events(&exit), vec![]);
+    }
+
+    #[test]
+    fn parses_multiple_and_truncated_messages()
+    {
+        let mut buffer = datagram(EXEC);
+        buffer.extend(datagram(EXIT));
+        assert_eq!(parse_proc_events(&buffer), vec![ProcEvent::Exec(14403), ProcEvent::Exit(14403)]);
+
+        buffer.truncate(datagram(EXEC).len() + 20);
+        assert_eq!(parse_proc_events(&buffer), vec![ProcEvent::Exec(14403)]);
+    }
+
+    #[test]
+    fn builds_control_message()
+    {
+        let message = get_control_message(PROC_CN_MCAST_LISTEN);
+        assert_eq!(message.len(), 40);
+        assert_eq!(read_u32(&message, 0), Some(40));
+        assert_eq!(read_u32(&message, 16), Some(CN_IDX_PROC));
+        assert_eq!(read_u32(&message, 20), Some(CN_VAL_PROC));
+        assert_eq!(&message[32..34], &4u16.to_ne_bytes());
+        assert_eq!(read_u32(&message, 36), Some(PROC_CN_MCAST_LISTEN));
+    }
+}
@@ -10,18 +10,32 @@ mod procdumpconfiguration;
 mod triggerthreadprocs;
 mod monitor;
 mod processhelpers;
+mod procconnector;
 mod dumpwriter;
+mod dumpworker;
+mod dumpmetadata;
+mod dumpcompressor;
+mod dumpmanifest;
+mod dumpname;
+mod configfile;
+mod daemon;
+mod signalhandler;
+mod systemdnotify;
+mod controlsocket;
+mod events;
+mod logging;
+mod hooks;
+mod metrics;
+mod samplerecorder;
 
 //use std::thread;
+use nix::sys::signal::Signal;
 
 // -----------------------------------------------------------------
 // Main function
 // -----------------------------------------------------------------
 fn main()
 {
-    procdumpconfiguration::print_banner();
-    procdumpconfiguration::init_procdump();
-
     // TODO: Check privilege warning
 
     // Parse cmd line
@@ -31,7 +45,86 @@ fn main()
         return;
     }
 
+    if !config.verify_directory.is_empty()
+    {
+        let verified = dumpmanifest::verify_manifest(&config.verify_directory);
+        std::process::exit(if verified { 0 } else { 1 });
+    }
+
+    if procdumpconfiguration::init_procdump(&config) < 0
+    {
+        return;
+    }
+
+    // Set up event output first, JSON mode may redirect the console
+    if !events::init_events(&config)
+    {
+        return;
+    }
+
+    procdumpconfiguration::print_banner();
+
+    // Profiles being monitored, shared with the control socket and signal handler
+    let profile_monitors: monitor::ProfileMonitorList = Default::default();
+
+    // Handle signals sent to procdump before any other thread is created
+    let signals = if config.daemon_mode
+    {
+        signalhandler::start_signal_thread(&[Signal::SIGUSR1, Signal::SIGHUP, Signal::SIGTERM, Signal::SIGINT], profile_monitors.clone())
+    }
+    else
+    {
+        signalhandler::start_signal_thread(&[Signal::SIGUSR1, Signal::SIGTERM, Signal::SIGINT], profile_monitors.clone())
+    };
+
+    if !dumpworker::start_dump_workers(config.max_concurrent_dumps)
+    {
+        return;
+    }
+
+    if !config.metrics_address.is_empty() && !metrics::start_metrics_server(&config.metrics_address, profile_monitors.clone())
+    {
+        return;
+    }
+
+    // Long running service driven by a configuration file
+    if config.daemon_mode
+    {
+        daemon::run_daemon(&config, &profile_monitors, signals);
+        return;
+    }
+
+    // Only the daemon listens for signals, Ctrl-C exits once dumps are cancelled
+    drop(signals);
+
+    // Monitoring profiles from a configuration file or the command line
+    let profiles = if !config.config_file_path.is_empty()
+    {
+        match configfile::load_profiles(&config.config_file_path)
+        {
+            Ok(profiles) => profiles,
+            Err(e) => { println!("{}", e); return; },
+        }
+    }
+    else
+    {
+        vec![config.clone()]
+    };
+
+    dumpwriter::remove_partial_dumps(&profiles);
+
+    if !config.control_socket_path.is_empty() && !controlsocket::start_control_socket(&config.control_socket_path, profile_monitors.clone())
+    {
+        return;
+    }
+
     // Start monitoring based on config
-    monitor::monitor_processes(&mut config);
+    monitor::monitor_profiles(profiles, &profile_monitors);
+    dumpworker::wait_for_queued_dumps();
+
+    if !config.control_socket_path.is_empty()
+    {
+        controlsocket::stop_control_socket(&config.control_socket_path);
+    }
 }
 
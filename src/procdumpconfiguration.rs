@@ -6,21 +6,70 @@
 // Manages the procdump configuration
 //
 //--------------------------------------------------------------------
+use crate::dumpname::{is_dump_name_template, validate_dump_name_template};
+use crate::logging::enable_logging;
+use crate::processhelpers::{HistorySample, ProcessHandle, ProcessSample};
+use serde::Serialize;
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
 //
 // Constants used
 //
 const DEFAULT_POLLING_INTERVAL: u64 = 1000;     // ms
 const DEFAULT_DELTA_TIME: u32 = 10;             // secs
+const DEFAULT_SAMPLE_FILE_SIZE: u64 = 10;       // MB
+const DEFAULT_HOOK_TIMEOUT: u64 = 60;           // secs
 const DEFAULT_NUMBER_OF_DUMPS: u32 = 1;
+const DEFAULT_MAX_CONCURRENT_DUMPS: u32 = 2;
+
+// Options of a monitoring profile, with -config they come from the configuration file
+const PROFILE_OPTIONS: [&str; 28] = ["m", "ml", "c", "cl", "tc", "fc", "sig", "n", "s", "pf", "o", "w", "pgid", "csv", "csvsize",
+    "redactenv", "history", "z", "minfree", "dumptimeout", "maxdumps", "maxsize", "maxage", "pre-cmd", "pre-cmd-timeout",
+    "post-cmd", "post-cmd-timeout", "post-cmd-wait"];
+
+//
+// Compression applied to dumps once gcore has written them
+//
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpCompression
+{
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl DumpCompression
+{
+    pub fn parse(name: &str) -> Option<DumpCompression>
+    {
+        match name
+        {
+            "zstd" => Some(DumpCompression::Zstd),
+            "gzip" => Some(DumpCompression::Gzip),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            DumpCompression::None => "none",
+            DumpCompression::Zstd => "zstd",
+            DumpCompression::Gzip => "gzip",
+        }
+    }
+}
 
 //
 // Struct used to communicate the current procdump configuration
 //
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Serialize)]
 pub struct ProcDumpConfiguration{
     pub process_id: i32,
     pub process_pgid: i32,
@@ -50,6 +99,38 @@ pub struct ProcDumpConfiguration{
     pub active: bool,
     pub process_terminated: bool,
     pub is_quit: bool,
+    pub config_file_path: String,
+    pub verify_directory: String,
+    pub profile_name: String,
+    pub daemon_mode: bool,
+    pub control_socket_path: String,
+    pub paused: bool,
+    pub last_sample: ProcessSample,
+    pub json_output: bool,
+    pub json_output_path: String,
+    pub metrics_address: String,
+    pub sample_file_enabled: bool,
+    pub sample_file_max_size: u64,
+    pub redacted_environment: Vec<String>,
+    pub history_seconds: u32,
+    pub dump_compression: DumpCompression,
+    pub min_free_space: u64,
+    pub retention_max_dumps: u32,
+    pub retention_max_size: u64,
+    pub retention_max_age: u64,
+    pub pre_dump_command: String,
+    pub pre_dump_timeout: u64,
+    pub post_dump_command: String,
+    pub post_dump_timeout: u64,
+    pub post_dump_wait: bool,
+    pub max_concurrent_dumps: u32,
+    pub dump_timeout: u64,
+    #[serde(skip)]
+    pub sample_history: VecDeque<HistorySample>,
+    #[serde(skip)]
+    pub process_handle: Option<Arc<ProcessHandle>>,
+    #[serde(skip)]
+    pub monitor_cancelled: bool,
 }
 
 //--------------------------------------------------------------------
@@ -73,6 +154,46 @@ pub fn apply_defaults(config:&mut ProcDumpConfiguration)
     {
         config.polling_frequency = DEFAULT_POLLING_INTERVAL;
     }
+
+    if config.sample_file_max_size == u64::MAX
+    {
+        config.sample_file_max_size = DEFAULT_SAMPLE_FILE_SIZE;
+    }
+
+    if config.pre_dump_timeout == u64::MAX
+    {
+        config.pre_dump_timeout = DEFAULT_HOOK_TIMEOUT;
+    }
+
+    if config.post_dump_timeout == u64::MAX
+    {
+        config.post_dump_timeout = DEFAULT_HOOK_TIMEOUT;
+    }
+}
+
+//--------------------------------------------------------------------
+//
+// retune_configuration - Applies the settings of a (reloaded) profile
+// to the configuration of a monitored process, keeping its runtime state
+//
+//--------------------------------------------------------------------
+pub fn retune_configuration(config:&mut ProcDumpConfiguration, profile: &ProcDumpConfiguration)
+{
+    let mut retuned = profile.clone();
+    retuned.process_id = config.process_id;
+    retuned.process_name = config.process_name.clone();
+    retuned.process_start_time = config.process_start_time;
+    retuned.number_of_dumps_collected = config.number_of_dumps_collected;
+    retuned.process_terminated = config.process_terminated;
+    retuned.is_quit = config.is_quit;
+    retuned.gcore_process_id = config.gcore_process_id;
+    retuned.active = config.active;
+    retuned.paused = config.paused;
+    retuned.last_sample = config.last_sample.clone();
+    retuned.sample_history = config.sample_history.clone();
+    retuned.process_handle = config.process_handle.clone();
+
+    *config = retuned;
 }
 
 // -----------------------------------------------------------------
@@ -127,6 +248,35 @@ impl Default for ProcDumpConfiguration
             overwrite_existing_dump: false,
             process_terminated: false,
             is_quit: false,
+            config_file_path: Default::default(),
+            verify_directory: Default::default(),
+            profile_name: Default::default(),
+            daemon_mode: false,
+            control_socket_path: Default::default(),
+            paused: false,
+            last_sample: Default::default(),
+            json_output: false,
+            json_output_path: Default::default(),
+            metrics_address: Default::default(),
+            sample_file_enabled: false,
+            sample_file_max_size: u64::MAX,
+            redacted_environment: Vec::new(),
+            history_seconds: 0,
+            dump_compression: DumpCompression::None,
+            min_free_space: 0,
+            retention_max_dumps: u32::MAX,
+            retention_max_size: u64::MAX,
+            retention_max_age: u64::MAX,
+            pre_dump_command: Default::default(),
+            pre_dump_timeout: u64::MAX,
+            post_dump_command: Default::default(),
+            post_dump_timeout: u64::MAX,
+            post_dump_wait: false,
+            max_concurrent_dumps: DEFAULT_MAX_CONCURRENT_DUMPS,
+            dump_timeout: u64::MAX,
+            sample_history: VecDeque::new(),
+            process_handle: None,
+            monitor_cancelled: false,
         }
     }
 }
@@ -134,9 +284,14 @@ impl Default for ProcDumpConfiguration
 // -----------------------------------------------------------------
 // init_procdump - Initialize ProcDump
 // -----------------------------------------------------------------
-pub fn init_procdump() -> i32
+pub fn init_procdump(config: &ProcDumpConfiguration) -> i32
 {
     // Open logger
+    if config.diagnostics_logging_enabled && !enable_logging()
+    {
+        return -1;
+    }
+
     // Check kernel version
 
 
@@ -175,8 +330,22 @@ pub fn print_usage(){
     println!("            [-pf Polling_Frequency]");
     println!("            [-o]");
     println!("            [-log]");
+    println!("            [-control Socket_Path]");
+    println!("            [-json | -jsonfile File]");
+    println!("            [-metrics [Address:]Port]");
+    println!("            [-maxconcurrent Count]");
+    println!("            [-csv [-csvsize Max_Size_MB]]");
+    println!("            [-redactenv Name[,Name...]]");
+    println!("            [-history Seconds]");
+    println!("            [-z zstd|gzip]");
+    println!("            [-minfree Free_Space_MB]");
+    println!("            [-dumptimeout Seconds]");
+    println!("            [-maxdumps Count] [-maxsize Total_Size_MB] [-maxage Hours]");
+    println!("            [-pre-cmd Command [-pre-cmd-timeout Seconds]]");
+    println!("            [-post-cmd Command [-post-cmd-timeout Seconds] [-post-cmd-wait]]");
     println!("            {{");
-    println!("             {{{{[-w] Process_Name | [-pgid] PID}} [Dump_File | Dump_Folder]}}");
+    println!("             {{{{[-w] Process_Name | [-pgid] PID}} [Dump_File | Dump_Folder]}} |");
+    println!("             -config Config_File [-daemon]");
     println!("            }}");
     println!();
     println!("Options:");
@@ -191,9 +360,43 @@ pub fn print_usage(){
     println!("   -sig    Signal number to intercept to create a dump of the process.");
     println!("   -pf     Polling frequency.");
     println!("   -o      Overwrite existing dump file.");
-    println!("   -log    Writes extended ProcDump tracing to syslog.");
+    println!("   -log    Writes extended ProcDump tracing to syslog. Tracing is global, with -config it");
+    println!("           applies to all profiles.");
+    println!("   -control Listen for runtime management commands on the specified Unix domain socket.");
+    println!("   -json   Write events as JSON lines to stdout (console output goes to stderr).");
+    println!("   -jsonfile Write events as JSON lines to the specified file.");
+    println!("   -csv    Record every sample to a CSV file per process in the dump folder.");
+    println!("   -csvsize Size in MB at which the CSV file is rotated (default is 10).");
+    println!("   -redactenv Environment variables to redact in the dump metadata file (NAME or PREFIX*).");
+    println!("   -history Keep the samples of the specified number of seconds and write them with each dump.");
+    println!("   -z      Compress dumps with zstd or gzip after gcore has written them. The uncompressed");
+    println!("           dump and the compressed copy must both fit in the dump folder.");
+    println!("   -minfree Free space in MB to leave in the dump folder, dumps that don't fit are skipped.");
+    println!("   -dumptimeout Seconds after which a dump that hasn't completed is cancelled.");
+    println!("   -maxdumps Maximum number of dumps kept in the dump folder, the oldest are deleted.");
+    println!("   -maxsize Maximum total size in MB of the dumps kept in the dump folder.");
+    println!("   -maxage Maximum age in hours of the dumps kept in the dump folder.");
+    println!("   -pre-cmd Command run before each dump, a non-zero exit code skips the dump.");
+    println!("   -pre-cmd-timeout Seconds after which the pre dump command is killed and the dump written (default is 60).");
+    println!("   -post-cmd Command run after each dump, see PROCDUMP_* environment variables.");
+    println!("   -post-cmd-timeout Seconds after which the post dump command is killed (default is 60).");
+    println!("   -post-cmd-wait Wait for the post dump command before the dump is counted as collected.");
+    println!("   -metrics Serve Prometheus metrics on http://Address:Port/metrics (default address is 127.0.0.1).");
+    println!("   -maxconcurrent Maximum number of dumps written at the same time across all processes (default is 2).");
     println!("   -w      Wait for the specified process to launch if it's not running.");
     println!("   -pgid   Process ID specified refers to a process group ID.");
+    println!("   -config Load monitoring profiles from the specified TOML configuration file. Only -daemon,");
+    println!("           -log, -json, -jsonfile, -metrics, -control and -maxconcurrent can be combined with it.");
+    println!("   -daemon Keep running as a service, reload the configuration file on SIGHUP.");
+    println!();
+    println!("Dump_File may be a template using the tokens {{pid}}, {{name}}, {{trigger}}, {{counter}},");
+    println!("{{timestamp}}, {{epoch}}, {{hostname}}, {{uid}} and {{container}}, e.g. {{name}}_{{trigger}}_{{timestamp}}.core");
+    println!();
+    println!("Verify Usage:");
+    println!("   procdump verify [Dump_Folder]");
+    println!();
+    println!("   Re-hashes the dumps listed in the manifest of the dump folder (default is the current");
+    println!("   directory) and reports the missing and corrupted ones.");
     println!();
 }
 
@@ -211,7 +414,15 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
         return -1;
     }
 
+    // Verify the dumps of a folder against its manifest
+    if args[1] == "verify"
+    {
+        config.verify_directory = args.get(2).cloned().unwrap_or_else(|| ".".to_string());
+        return 0;
+    }
+
     let mut _i = 0;
+    let mut profile_option: Option<String> = None;
 
     while _i < args.len(){
 
@@ -220,6 +431,11 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
             continue;
         }
 
+        if profile_option.is_none() && (args[_i].starts_with('-') || args[_i].starts_with('/')) && PROFILE_OPTIONS.contains(&&args[_i][1..])
+        {
+            profile_option = Some(args[_i].clone());
+        }
+
         if args[_i].eq("/h") || args[_i].eq("-h") {
             print_usage();
             return -1;
@@ -320,9 +536,13 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
                 return -1;
             }
 
-            let s = args.get(_i+1).unwrap();
-
-            config.polling_frequency = s.parse::<u64>().unwrap();
+            config.polling_frequency = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(polling_frequency) if polling_frequency > 0 => polling_frequency,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
 
             _i+=1;
         }
@@ -342,6 +562,288 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
         {
             config.is_process_group_set = true;
         }
+        else if args[_i].eq("/config") || args[_i].eq("-config")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.config_file_path = args.get(_i+1).unwrap().clone();
+
+            _i+=1;
+        }
+        else if args[_i].eq("/control") || args[_i].eq("-control")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.control_socket_path = args.get(_i+1).unwrap().clone();
+
+            _i+=1;
+        }
+        else if args[_i].eq("/json") || args[_i].eq("-json") || args[_i].eq("--json")
+        {
+            config.json_output = true;
+        }
+        else if args[_i].eq("/jsonfile") || args[_i].eq("-jsonfile")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.json_output = true;
+            config.json_output_path = args.get(_i+1).unwrap().clone();
+
+            _i+=1;
+        }
+        else if args[_i].eq("/metrics") || args[_i].eq("-metrics")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            let address = args.get(_i+1).unwrap();
+            config.metrics_address = if address.contains(':') { address.clone() } else { format!("127.0.0.1:{}", address) };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/maxconcurrent") || args[_i].eq("-maxconcurrent")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.max_concurrent_dumps = match args.get(_i+1).unwrap().parse::<u32>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/csv") || args[_i].eq("-csv")
+        {
+            config.sample_file_enabled = true;
+        }
+        else if args[_i].eq("/csvsize") || args[_i].eq("-csvsize")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.sample_file_max_size = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(size) if size > 0 => size,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/redactenv") || args[_i].eq("-redactenv")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.redacted_environment.extend(args.get(_i+1).unwrap().split(',').filter(|name| !name.is_empty()).map(|name| name.to_string()));
+
+            _i+=1;
+        }
+        else if args[_i].eq("/history") || args[_i].eq("-history")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.history_seconds = match args.get(_i+1).unwrap().parse::<u32>() {
+                Ok(seconds) => seconds,
+                Err(_) => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/z") || args[_i].eq("-z")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.dump_compression = match DumpCompression::parse(args.get(_i+1).unwrap()) {
+                Some(compression) => compression,
+                None => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/minfree") || args[_i].eq("-minfree")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.min_free_space = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(size) => size,
+                Err(_) => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/dumptimeout") || args[_i].eq("-dumptimeout")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.dump_timeout = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(seconds) if seconds > 0 => seconds,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/maxdumps") || args[_i].eq("-maxdumps")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.retention_max_dumps = match args.get(_i+1).unwrap().parse::<u32>() {
+                Ok(count) if count > 0 => count,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/maxsize") || args[_i].eq("-maxsize")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.retention_max_size = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(size) => size,
+                Err(_) => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/maxage") || args[_i].eq("-maxage")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.retention_max_age = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(hours) => hours,
+                Err(_) => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/pre-cmd") || args[_i].eq("-pre-cmd")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.pre_dump_command = args.get(_i+1).unwrap().clone();
+
+            _i+=1;
+        }
+        else if args[_i].eq("/pre-cmd-timeout") || args[_i].eq("-pre-cmd-timeout")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.pre_dump_timeout = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(seconds) if seconds > 0 => seconds,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/post-cmd") || args[_i].eq("-post-cmd")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.post_dump_command = args.get(_i+1).unwrap().clone();
+
+            _i+=1;
+        }
+        else if args[_i].eq("/post-cmd-timeout") || args[_i].eq("-post-cmd-timeout")
+        {
+            if args.get(_i+1).is_none() {
+                print_usage();
+                return -1;
+            }
+
+            config.post_dump_timeout = match args.get(_i+1).unwrap().parse::<u64>() {
+                Ok(seconds) if seconds > 0 => seconds,
+                _ => {
+                    print_usage();
+                    return -1;
+                }
+            };
+
+            _i+=1;
+        }
+        else if args[_i].eq("/post-cmd-wait") || args[_i].eq("-post-cmd-wait")
+        {
+            config.post_dump_wait = true;
+        }
+        else if args[_i].eq("/daemon") || args[_i].eq("-daemon")
+        {
+            config.daemon_mode = true;
+        }
         else
         {
             //
@@ -414,6 +916,52 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
         _i+=1;
     }
 
+    // Daemon mode is driven by the profiles of a configuration file
+    if config.daemon_mode && config.config_file_path.is_empty()
+    {
+        println!("Daemon mode requires a configuration file (-config).");
+        print_usage();
+        return -1;
+    }
+
+    //
+    // Profiles come from the configuration file, they are validated when it is loaded
+    //
+    if !config.config_file_path.is_empty()
+    {
+        if process_specified
+        {
+            println!("Targets must be specified in the configuration file when using -config.");
+            print_usage();
+            return -1;
+        }
+
+        if let Some(option) = profile_option
+        {
+            println!("{} can't be used with -config, profile options must be specified in the configuration file.", option);
+            print_usage();
+            return -1;
+        }
+
+        return 0;
+    }
+
+    if validate_configuration(config) < 0
+    {
+        print_usage();
+        return -1;
+    }
+
+    0
+}
+
+// -----------------------------------------------------------------
+// validate_configuration - Validates a fully populated configuration
+// and applies defaults. Shared by the command line and the profiles
+// loaded from a configuration file.
+// -----------------------------------------------------------------
+pub fn validate_configuration(config: &mut ProcDumpConfiguration) -> i32
+{
     //
     // Validate multi arguments
     //
@@ -427,7 +975,7 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
     // Wait
     if config.waiting_process_name && config.process_id != i32::MAX
     {
-        print_usage();
+        println!("Waiting for a process can only be combined with a process name.");
         return -1;
     }
 
@@ -449,25 +997,32 @@ pub fn get_options(config: &mut ProcDumpConfiguration) -> i32
             (config.trigger_threshold_file_descriptors != u32::MAX)
         {
             println!("Signal trigger must be the only trigger specified.");
-            print_usage();
             return -1;
         }
 
         if config.polling_frequency != u64::MAX
         {
             println!("Polling interval has no meaning during signal monitoring.");
-            print_usage();
             return -1;
         }
 
         config.trigger_threshold_timer = false;
     }
 
-    // If we are monitoring multiple process, setting dump name doesn't make sense (path is OK)
-    if (config.is_process_group_set || config.waiting_process_name) && !config.core_dump_name.is_empty()
+    if is_dump_name_template(&config.core_dump_name)
     {
-        println!("Setting core dump name in multi process monitoring is invalid (path is ok).");
-        print_usage();
+        if let Err(e) = validate_dump_name_template(&config.core_dump_name)
+        {
+            println!("{}", e);
+            return -1;
+        }
+    }
+
+    // If we are monitoring multiple process, a dump name only makes sense as a template
+    // that tells the processes apart (path is OK)
+    if (config.is_process_group_set || config.waiting_process_name) && !config.core_dump_name.is_empty() && !config.core_dump_name.contains("{pid}")
+    {
+        println!("Setting core dump name in multi process monitoring is invalid unless it's a template containing {{pid}} (path is ok).");
         return -1;
     }
 
@@ -489,6 +1044,11 @@ pub fn print_configuration(config: &ProcDumpConfiguration)
         println!();
     }
 
+    if !config.profile_name.is_empty()
+    {
+        println!("Profile: {}", config.profile_name);
+    }
+
     //
     // Process target
     //
@@ -584,7 +1144,11 @@ pub fn print_configuration(config: &ProcDumpConfiguration)
     println!("Number of dumps: {}", config.number_of_dumps_to_collect);
     println!("Output Directory: {}", config.core_dump_path);
 
-    if !config.core_dump_name.is_empty()
+    if is_dump_name_template(&config.core_dump_name)
+    {
+        println!("Dump name template: {}", config.core_dump_name);
+    }
+    else if !config.core_dump_name.is_empty()
     {
         println!("Custom name for core dumps: {}_<counter>.<pid>", config.core_dump_name);
     }
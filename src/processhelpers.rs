@@ -6,7 +6,123 @@
 // Helpers for retrieving process stats
 //
 //--------------------------------------------------------------------
+extern crate nix;
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
+use serde::Serialize;
 use std::{fs};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Instant;
+
+//
+// Latest values sampled by the monitor threads of a process
+//
+#[derive(Clone, Default, PartialEq, Serialize)]
+pub struct ProcessSample
+{
+    pub cpu_usage: Option<u32>,         // %
+    pub memory_usage: Option<i64>,      // MB
+    pub thread_count: Option<i64>,
+    pub file_descriptor_count: Option<u32>,
+}
+
+//
+// A sample kept in the pre-trigger history of a process
+//
+#[derive(Clone, PartialEq, Serialize)]
+pub struct HistorySample
+{
+    pub timestamp: String,
+    #[serde(skip)]
+    pub taken: Instant,
+    #[serde(flatten)]
+    pub sample: ProcessSample,
+}
+
+//
+// A process that can't be confused with a later process reusing its PID.
+// Backed by a pidfd where the kernel supports it (5.3+), by the start
+// time of the process otherwise.
+//
+pub struct ProcessHandle
+{
+    pub pid: i32,
+    pub start_time: u64,
+    pidfd: Option<OwnedFd>,
+}
+
+impl ProcessHandle
+{
+    pub fn open(pid: i32) -> ProcessHandle
+    {
+        // Pin the process first so the start time read next can't be the one of a later process
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        let pidfd = if fd >= 0 { Some(unsafe { OwnedFd::from_raw_fd(fd as i32) }) } else { None };
+        let mut handle = ProcessHandle { pid, start_time: get_process_start_time(pid), pidfd };
+
+        // The start time can't be trusted if the process exited before it was read
+        if handle.pidfd.is_some() && handle.has_exited()
+        {
+            handle.start_time = u64::MAX;
+        }
+
+        handle
+    }
+
+    // Returns true once the process has exited, even if its PID was reused
+    pub fn has_exited(&self) -> bool
+    {
+        match &self.pidfd
+        {
+            // A pidfd becomes readable when the process exits
+            Some(pidfd) =>
+            {
+                let mut fds = [PollFd::new(pidfd.as_raw_fd(), PollFlags::POLLIN)];
+                matches!(poll(&mut fds, 0), Ok(ready) if ready > 0)
+            },
+            None => kill(Pid::from_raw(self.pid), None) == Err(Errno::ESRCH) || get_process_start_time(self.pid) != self.start_time,
+        }
+    }
+
+    // Returns true if another process is running with the PID of the process
+    pub fn is_pid_reused(&self) -> bool
+    {
+        let start_time = get_process_start_time(self.pid);
+        start_time != u64::MAX && start_time != self.start_time
+    }
+
+    // Sends a signal to the process, never to a process reusing its PID
+    pub fn send_signal(&self, signal: Signal) -> nix::Result<()>
+    {
+        match &self.pidfd
+        {
+            Some(pidfd) =>
+            {
+                let res = unsafe { libc::syscall(libc::SYS_pidfd_send_signal, pidfd.as_raw_fd(), signal as libc::c_int, std::ptr::null::<libc::siginfo_t>(), 0) };
+                match Errno::result(res)
+                {
+                    // Kernel without pidfd_send_signal
+                    Err(Errno::ENOSYS) if !self.has_exited() => kill(Pid::from_raw(self.pid), signal),
+                    result => result.map(drop),
+                }
+            },
+            None if self.has_exited() => Err(Errno::ESRCH),
+            None => kill(Pid::from_raw(self.pid), signal),
+        }
+    }
+}
+
+// Handles are equal if they refer to the same process
+impl PartialEq for ProcessHandle
+{
+    fn eq(&self, other: &ProcessHandle) -> bool
+    {
+        self.pid == other.pid && self.start_time == other.start_time
+    }
+}
 
 //--------------------------------------------------------------------
 //
@@ -91,7 +207,7 @@ pub fn get_process_pgid(pid: i32) -> u64
         Err(_) => return u64::MAX,
     };
 
-    let pgid = statcontents.split(" ").nth(4).unwrap().parse::<u64>().unwrap();
+    let pgid = split_stat_fields(&statcontents).and_then(|fields| fields.get(4)?.parse::<u64>().ok()).unwrap_or(u64::MAX);
 
     return pgid;
 }
@@ -109,11 +225,39 @@ pub fn get_process_start_time(pid: i32) -> u64
         Err(_) => return u64::MAX,
     };
 
-    let start_time = statcontents.split(" ").nth(21).unwrap().parse::<u64>().unwrap();
+    let start_time = split_stat_fields(&statcontents).and_then(|fields| fields.get(21)?.parse::<u64>().ok()).unwrap_or(u64::MAX);
 
     return start_time;
 }
 
+//--------------------------------------------------------------------
+//
+// split_stat_fields - splits the contents of /proc/<pid>/stat into its
+// fields, numbered as in proc(5) from 0. The command name may contain
+// spaces and parentheses so the fields are split after its last ')'.
+//--------------------------------------------------------------------
+pub fn split_stat_fields(stat: &str) -> Option<Vec<&str>>
+{
+    let (head, tail) = stat.rsplit_once(')')?;
+    let (pid, comm) = head.split_once(" (")?;
+
+    let mut fields = vec![pid, comm];
+    fields.extend(tail.split_whitespace());
+
+    Some(fields)
+}
+
+//--------------------------------------------------------------------
+//
+// get_system_uptime - returns the number of seconds since boot, None
+// if /proc/uptime can't be read.
+//--------------------------------------------------------------------
+pub fn get_system_uptime() -> Option<f64>
+{
+    let uptime = fs::read_to_string("/proc/uptime").ok()?;
+    uptime.split_whitespace().next()?.parse::<f64>().ok()
+}
+
 //--------------------------------------------------------------------
 //
 // is_process_running - returns true if the specified process is
@@ -127,4 +271,137 @@ pub fn is_process_running(pid: i32) -> bool
         Ok(_) => return true,
         Err(_) => return false,
     };
-}
\ No newline at end of file
+}
+//--------------------------------------------------------------------
+//
+// estimate_dump_size - returns the estimated size in bytes of a core
+// dump of the specified process based on its mappings and coredump
+// filter, None if the process can't be inspected.
+//
+//--------------------------------------------------------------------
+pub fn estimate_dump_size(pid: i32) -> Option<u64>
+{
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid)).ok()?;
+
+    // Default filter of the kernel: anonymous private/shared, ELF headers, private huge pages
+    let filter = fs::read_to_string(format!("/proc/{}/coredump_filter", pid)).ok()
+        .and_then(|filter| u32::from_str_radix(filter.trim(), 16).ok())
+        .unwrap_or(0x33);
+
+    let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE).ok().flatten().unwrap_or(4096) as u64;
+
+    // Notes (registers, auxv, file mappings) and program headers
+    let mut size: u64 = 64 * 1024;
+    for line in maps.lines()
+    {
+        // address perms offset dev inode [pathname]
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 5
+        {
+            continue;
+        }
+
+        let (start, end) = match fields[0].split_once('-')
+        {
+            Some((start, end)) => match (u64::from_str_radix(start, 16), u64::from_str_radix(end, 16))
+            {
+                (Ok(start), Ok(end)) => (start, end),
+                _ => continue,
+            },
+            None => continue,
+        };
+
+        let perms = fields[1].as_bytes();
+        let pathname = fields.get(5).copied().unwrap_or("");
+        if perms[0] != b'r' || pathname == "[vsyscall]" || pathname == "[vvar]"
+        {
+            continue;
+        }
+
+        let shared = perms[3] == b's';
+        let huge = pathname.contains("anon_hugepage");
+        let anonymous = fields[4] == "0" || huge;
+        let bit = match (anonymous, shared, huge)
+        {
+            (true, false, false) => 0,
+            (true, true, false) => 1,
+            (false, false, _) => 2,
+            (false, true, _) => 3,
+            (_, false, true) => 5,
+            (_, true, true) => 6,
+        };
+
+        if filter & (1 << bit) != 0
+        {
+            size += end - start;
+        }
+        else if !anonymous && filter & (1 << 4) != 0 && fields[2].trim_start_matches('0').is_empty()
+        {
+            // Only the ELF header page of file mappings is dumped
+            size += page_size;
+        }
+    }
+
+    Some(size)
+}
+
+//--------------------------------------------------------------------
+//
+// is_process_stopped - returns true if the specified process is
+// stopped by a signal or a tracer, false otherwise.
+//
+//--------------------------------------------------------------------
+pub fn is_process_stopped(pid: i32) -> bool
+{
+    // The state follows the parenthesized command name, which may contain spaces
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+    match stat.rsplit_once(')')
+    {
+        Some((_, fields)) => matches!(fields.trim_start().chars().next(), Some('T') | Some('t')),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // /proc/<pid>/stat of cat (PID 20615), the fields after the command name
+    const STAT_TAIL: &str = "R 20611 20615 20611 0 -1 4194304 81 0 0 0 0 0 0 0 20 0 1 0 379863 2703360 313 18446744073709551615 94525038166016 94525038185897 140732442142336 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 94525038201904 94525038203520 94525770448896 140732442146214 140732442146234 140732442146234 140732442148843 0\n";
+
+    #[test]
+    fn splits_stat_fields()
+    {
+        let stat = format!("20615 (cat) {}", STAT_TAIL);
+        let fields = split_stat_fields(&stat).unwrap();
+        assert_eq!(fields[0], "20615");
+        assert_eq!(fields[1], "cat");
+        assert_eq!(fields[2], "R");
+        assert_eq!(fields[4], "20615");
+        assert_eq!(fields[19], "1");
+        assert_eq!(fields[21], "379863");
+        assert_eq!(fields[23], "313");
+        assert_eq!(fields.len(), 52);
+    }
+
+    #[test]
+    fn splits_stat_fields_of_command_names_with_parentheses()
+    {
+        // The command name can contain anything, including ") ("
+        let stat = format!("20615 (Web Content) (x) ) {}", STAT_TAIL);
+        let fields = split_stat_fields(&stat).unwrap();
+        assert_eq!(fields[1], "Web Content) (x) ");
+        assert_eq!(fields[2], "R");
+        assert_eq!(fields[21], "379863");
+        assert_eq!(fields.len(), 52);
+    }
+
+    #[test]
+    fn rejects_malformed_stat()
+    {
+        assert_eq!(split_stat_fields(""), None);
+        assert_eq!(split_stat_fields("20615 cat R 20611"), None);
+        assert_eq!(split_stat_fields("20615cat) R 20611"), None);
+    }
+}
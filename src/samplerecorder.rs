@@ -0,0 +1,96 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Records the samples of the monitor threads (-csv) to a CSV file per
+// monitored process, next to the dumps
+//
+//--------------------------------------------------------------------
+use crate::logging::{Severity, log};
+use crate::procdumpconfiguration::ProcDumpConfiguration;
+use chrono::Local;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+const SAMPLE_FILE_HEADER: &str = "timestamp,pid,process_name,cpu_percent,memory_mb,threads,file_descriptors";
+
+// Number of rotated sample files kept (name.csv.1 .. name.csv.N)
+const SAMPLE_FILE_ROTATIONS: u32 = 5;
+
+// Serializes writers, the monitor threads of a process share its file
+static SAMPLE_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+// --------------------------------------------------------------------
+// get_sample_file_path - Returns the sample file of a monitored process
+// --------------------------------------------------------------------
+pub fn get_sample_file_path(config: &ProcDumpConfiguration) -> String
+{
+    format!("{}/{}_{}_samples.csv", config.core_dump_path, config.process_name, config.process_id)
+}
+
+// --------------------------------------------------------------------
+// record_sample_row - Appends the latest sample of a monitored process
+// to its sample file, rotating the file once it reaches the maximum size
+// --------------------------------------------------------------------
+pub fn record_sample_row(config: &ProcDumpConfiguration)
+{
+    let _guard = SAMPLE_FILE_LOCK.lock().unwrap();
+    let path = get_sample_file_path(config);
+
+    let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+    if size >= config.sample_file_max_size * 1024 * 1024
+    {
+        rotate_sample_files(&path);
+    }
+
+    let mut file = match OpenOptions::new().create(true).append(true).open(&path)
+    {
+        Ok(file) => file,
+        Err(e) =>
+        {
+            log(Severity::Warning, &format!("Failed to open sample file {}: {}", path, e));
+            return;
+        },
+    };
+
+    let sample = &config.last_sample;
+    let mut row = String::new();
+    if file.metadata().map(|metadata| metadata.len()).unwrap_or(0) == 0
+    {
+        row.push_str(SAMPLE_FILE_HEADER);
+        row.push('\n');
+    }
+
+    row.push_str(&format!("{},{},{},{},{},{},{}\n",
+        Local::now().to_rfc3339(),
+        config.process_id,
+        config.process_name.replace([',', '\n'], "_"),
+        sample.cpu_usage.map_or(String::new(), |v| v.to_string()),
+        sample.memory_usage.map_or(String::new(), |v| v.to_string()),
+        sample.thread_count.map_or(String::new(), |v| v.to_string()),
+        sample.file_descriptor_count.map_or(String::new(), |v| v.to_string())));
+
+    if let Err(e) = file.write_all(row.as_bytes())
+    {
+        log(Severity::Warning, &format!("Failed to write sample file {}: {}", path, e));
+    }
+}
+
+// --------------------------------------------------------------------
+// rotate_sample_files - Shifts name.csv to name.csv.1, name.csv.1 to
+// name.csv.2 and so on, dropping the oldest file
+// --------------------------------------------------------------------
+fn rotate_sample_files(path: &String)
+{
+    for i in (1..SAMPLE_FILE_ROTATIONS).rev()
+    {
+        let _ = fs::rename(format!("{}.{}", path, i), format!("{}.{}", path, i + 1));
+    }
+
+    if let Err(e) = fs::rename(path, format!("{}.1", path))
+    {
+        log(Severity::Warning, &format!("Failed to rotate sample file {}: {}", path, e));
+    }
+}
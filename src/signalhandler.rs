@@ -0,0 +1,90 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// Signal handling for procdump itself
+//
+//--------------------------------------------------------------------
+extern crate nix;
+use crate::dumpworker::{cancel_dumps, is_dump_cancelled};
+use crate::logging::{Severity, log};
+use crate::monitor::{ProfileMonitorList, dump_monitored_processes};
+use nix::sys::signal::{SigSet, Signal};
+use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// --------------------------------------------------------------------
+// start_signal_thread - Blocks the specified signals and starts a thread
+// that waits for them. SIGUSR1 writes a manual dump of every monitored
+// process, SIGINT and SIGTERM cancel the dumps being written and are
+// then forwarded on the returned channel along with all other signals.
+// If nobody is listening, or on a second SIGINT/SIGTERM, procdump exits.
+// Must be called before any other thread is created so that all threads
+// inherit the signal mask.
+// --------------------------------------------------------------------
+pub fn start_signal_thread(signals: &[Signal], profile_monitors: ProfileMonitorList) -> Receiver<Signal>
+{
+    let mut sigset = SigSet::empty();
+    for signal in signals
+    {
+        sigset.add(*signal);
+    }
+
+    sigset.thread_block().expect("Failed to block signals.");
+
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new().name("Signal handler thread".to_string()).spawn(move ||
+    {
+        loop
+        {
+            let signal = match sigset.wait()
+            {
+                Ok(signal) => signal,
+                Err(_) => continue,
+            };
+
+            if signal == Signal::SIGUSR1
+            {
+                println!("SIGUSR1 received, writing a dump of all monitored processes");
+                dump_monitored_processes(&profile_monitors);
+                continue;
+            }
+
+            if signal == Signal::SIGINT || signal == Signal::SIGTERM
+            {
+                // A second signal doesn't wait for the dumps being cancelled
+                if is_dump_cancelled()
+                {
+                    println!("Exiting without waiting for the dumps being cancelled");
+                    process::exit(128 + signal as i32);
+                }
+
+                // Cancel on another thread so a second signal is still received
+                let sender = sender.clone();
+                let cancel = move ||
+                {
+                    cancel_dumps();
+                    if sender.send(signal).is_err()
+                    {
+                        // Not in daemon mode, exit as the signal would have
+                        process::exit(128 + signal as i32);
+                    }
+                };
+
+                if let Err(e) = thread::Builder::new().name("Cancel dumps thread".to_string()).spawn(cancel.clone())
+                {
+                    log(Severity::Error, &format!("Failed to create cancel dumps thread: {}", e));
+                    cancel();
+                }
+
+                continue;
+            }
+
+            let _ = sender.send(signal);
+        }
+    }).expect("Failed to create signal handler thread.");
+
+    receiver
+}
@@ -0,0 +1,70 @@
+// Copyright (c) Microsoft Corporation. All rights reserved.
+// Licensed under the MIT License
+
+//--------------------------------------------------------------------
+//
+// systemd service notifications (sd_notify protocol over NOTIFY_SOCKET)
+//
+//--------------------------------------------------------------------
+use std::env;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+// --------------------------------------------------------------------
+// notify - Sends a state string (e.g. "READY=1") to the service manager.
+// Returns false if we're not running under systemd or the send failed.
+// --------------------------------------------------------------------
+pub fn notify(state: &str) -> bool
+{
+    let notify_socket = match env::var("NOTIFY_SOCKET")
+    {
+        Ok(notify_socket) if !notify_socket.is_empty() => notify_socket,
+        _ => return false,
+    };
+
+    // A leading '@' refers to a socket in the abstract namespace
+    let addr = match notify_socket.strip_prefix('@')
+    {
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes()),
+        None => SocketAddr::from_pathname(&notify_socket),
+    };
+
+    let addr = match addr
+    {
+        Ok(addr) => addr,
+        Err(_) => return false,
+    };
+
+    let socket = match UnixDatagram::unbound()
+    {
+        Ok(socket) => socket,
+        Err(_) => return false,
+    };
+
+    socket.send_to_addr(state.as_bytes(), &addr).is_ok()
+}
+
+// --------------------------------------------------------------------
+// watchdog_interval - Returns the interval at which the watchdog should
+// be pinged (half of WATCHDOG_USEC), None if the watchdog is disabled.
+// --------------------------------------------------------------------
+pub fn watchdog_interval() -> Option<Duration>
+{
+    // WATCHDOG_PID, when set, must refer to us
+    if let Ok(watchdog_pid) = env::var("WATCHDOG_PID")
+    {
+        if watchdog_pid.parse::<u32>().ok() != Some(std::process::id())
+        {
+            return None;
+        }
+    }
+
+    let watchdog_usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if watchdog_usec == 0
+    {
+        return None;
+    }
+
+    Some(Duration::from_micros(watchdog_usec / 2))
+}
@@ -3,19 +3,26 @@
 
 //--------------------------------------------------------------------
 //
-// Contains all monitor trigger threads
+// Contains the monitor threads, the sampler evaluating the triggers of
+// a process and the signal monitor
 //
 //--------------------------------------------------------------------
 extern crate nix;
-extern crate sysinfo;
-use crate::dumpwriter::write_dump;
+use chrono::Local;
+use crate::dumpworker::queue_dump;
+use crate::dumpwriter::TriggerContext;
+use crate::logging::{Severity, is_logging_enabled, log};
 use crate::procdumpconfiguration::ProcDumpConfiguration;
+use crate::processhelpers::{HistorySample, ProcessSample, get_system_uptime, split_stat_fields};
+use crate::samplerecorder::record_sample_row;
 use std::fs;
 use std::thread::park_timeout;
 use std::time::{Instant, Duration};
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Receiver;
+use nix::errno::Errno;
+use nix::sys::signal::kill;
 use nix::unistd::*;
-use sysinfo::*;
 
 // --------------------------------------------------------------------
 // should_continue_monitoring - returns true if monitor thread should
@@ -23,7 +30,7 @@ use sysinfo::*;
 // --------------------------------------------------------------------
 pub fn should_continue_monitoring(config: &Arc<Mutex<ProcDumpConfiguration>>) -> bool
 {
-    let lock = config.lock().unwrap();
+    let mut lock = config.lock().unwrap();
 
     // Have we exceeded dump count?
     if lock.number_of_dumps_collected >= lock.number_of_dumps_to_collect
@@ -37,397 +44,374 @@ pub fn should_continue_monitoring(config: &Arc<Mutex<ProcDumpConfiguration>>) ->
         return false;
     }
 
-    // check if any process are running with PGID
-    if lock.process_pgid != i32::MAX
+    // check if any process are running with PGID (EPERM means it exists but isn't ours)
+    if lock.process_pgid != i32::MAX && kill(nix::unistd::Pid::from_raw(-lock.process_pgid), None) == Err(Errno::ESRCH)
     {
-/*       let pgid = Pid::from_raw(-1 * lock.process_pgid);
-      let res = kill(pgid, None);
-        if res.is_err()
-        {
-            println!("Error");
-            lock.process_terminated = true;
-            return false;
-        }*/
+        set_process_terminated(&mut lock);
+        return false;
     }
 
-    // check if any process are running with PID
-    if lock.process_id != i32::MAX
+    // check if the process is still running, the PID may have been reused
+    let exited = match &lock.process_handle
     {
-        /*
-            let pid = Pid::from_raw(lock.process_id);
+        Some(process_handle) => process_handle.has_exited(),
+        None => lock.process_id != i32::MAX && kill(nix::unistd::Pid::from_raw(lock.process_id), None) == Err(Errno::ESRCH),
+    };
 
-        let res = kill(pid, None);
-        if res.is_err()
-        {
-            println!("Target process {} is no longer alive", lock.process_id);
-            lock.process_terminated = true;
-            return false;
-        }*/
+    if exited
+    {
+        set_process_terminated(&mut lock);
+        return false;
     }
 
     true
 }
 
 // --------------------------------------------------------------------
-// cpu_monitoring_thread - Monitors for cpu consumption based on config
+// set_process_terminated - Marks the target process as gone so that all
+// monitor threads of the process exit. The exit is reported by the
+// monitor once the threads are done.
 // --------------------------------------------------------------------
-pub fn cpu_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
+fn set_process_terminated(config: &mut ProcDumpConfiguration)
 {
-    let lock = config.lock().unwrap();
-    let timeout = lock.polling_frequency/1000;
-    let in_between_dumps = lock.threshold_seconds;
-    let pid = lock.process_id;
-    let trigger_below = lock.trigger_threshold_mem_below;
-    let trigger_threshold = lock.trigger_threshold_cpu;
-    drop(lock);
-
-    let mut trigger_type = String::new();
-    trigger_type.push_str("cpu");
-
-    let hz: i64 = nix::unistd::sysconf(SysconfVar::CLK_TCK).unwrap().unwrap();
-
-    let mut sys = System::new_all();
-
-    while should_continue_monitoring(&config)
+    if !config.process_terminated
     {
-        // Read /proc/{pid}/stat file to get process statistics
-        let stat_path = format!("/proc/{}/stat", pid);
-        let statcontents = fs::read_to_string(stat_path).expect("Stat file not found.");
+        config.process_terminated = true;
+        log(Severity::Debug, &format!("Target process {} is no longer alive", config.process_id));
+    }
+}
 
-        // Get proc stats for CPU
-        let utime = statcontents.split(" ").nth(13).unwrap().parse::<u64>().unwrap();
-        let stime = statcontents.split(" ").nth(14).unwrap().parse::<u64>().unwrap();
-        let starttime = statcontents.split(" ").nth(21).unwrap().parse::<u64>().unwrap();
-        sys.refresh_all();
-        let uptime = sys.uptime();
+// --------------------------------------------------------------------
+// target_exited - Called by a monitor thread that failed to sample the
+// target, returns true if that's because the target is gone
+// --------------------------------------------------------------------
+fn target_exited(config: &Arc<Mutex<ProcDumpConfiguration>>) -> bool
+{
+    !should_continue_monitoring(config)
+}
 
-        let total_time = (utime + stime) / u64::try_from(hz).unwrap();
-        let elapsed_time = uptime - (starttime/u64::try_from(hz).unwrap());
-        let cpu_usage = ((total_time as f64/elapsed_time as f64) * 100 as f64)  as u32;
+// --------------------------------------------------------------------
+// is_monitor_paused - returns true if the monitor has been paused, in
+// which case it keeps sampling but doesn't trigger dumps
+// --------------------------------------------------------------------
+pub fn is_monitor_paused(config: &Arc<Mutex<ProcDumpConfiguration>>) -> bool
+{
+    config.lock().unwrap().paused
+}
 
-        if (trigger_below && cpu_usage < trigger_threshold.into()) || (!trigger_below && cpu_usage >= trigger_threshold.into())
-        {
-            println!("Trigger: CPU usage:{}% on process ID: {}", cpu_usage, pid);
-            write_dump(&config, &trigger_type);
-            if !should_continue_monitoring(&config)
-            {
-                // We've reached a stop state, exit
-                break;
-            }
+// --------------------------------------------------------------------
+// record_sample - Stores the latest value sampled by a monitor thread
+// and records it to the sample history and sample file if enabled
+// --------------------------------------------------------------------
+fn record_sample(config: &Arc<Mutex<ProcDumpConfiguration>>, update: impl FnOnce(&mut ProcessSample))
+{
+    let mut lock = config.lock().unwrap();
+    update(&mut lock.last_sample);
 
-            // Wait for time between dumps
-            let timeout_remaining = in_between_dumps;
-            let elapsed = park_thread(timeout_remaining.into());
-            if elapsed < Duration::from_secs(in_between_dumps.into())
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
-        }
-        else
+    if lock.history_seconds > 0
+    {
+        let sample = HistorySample { timestamp: Local::now().to_rfc3339(), taken: Instant::now(), sample: lock.last_sample.clone() };
+        let history_duration = Duration::from_secs(lock.history_seconds.into());
+        lock.sample_history.push_back(sample);
+        while lock.sample_history.front().is_some_and(|oldest| oldest.taken.elapsed() > history_duration)
         {
-            // Wait for polling frequency
-            let timeout_remaining = timeout;
-            let elapsed = park_thread(timeout_remaining);
-            if elapsed < Duration::from_secs(timeout)
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
+            lock.sample_history.pop_front();
         }
     }
 
-    let mut lock = config.lock().unwrap();
-    lock.is_quit = true;
-    0
+    if lock.sample_file_enabled
+    {
+        record_sample_row(&lock);
+    }
 }
 
 // --------------------------------------------------------------------
-// thread_monitoring_thread - Monitors for thread count  based on config
+// trace_sample - Traces a sample evaluated by a monitor thread
 // --------------------------------------------------------------------
-pub fn thread_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
+fn trace_sample(pid: i32, trigger_type: &str, value: i64, threshold: u32, trigger_below: bool)
 {
-    let lock = config.lock().unwrap();
-    let trigger_thread_threshold = lock.trigger_threshold_threads;
-    let timeout = lock.polling_frequency/1000;
-    let in_between_dumps = lock.threshold_seconds;
-    let pid = lock.process_id;
-    drop(lock);
-
-    let mut trigger_type = String::new();
-    trigger_type.push_str("threads");
+    if is_logging_enabled()
+    {
+        log(Severity::Debug, &format!("Sample for process {}: {}={} (trigger {} {})", pid, trigger_type, value, if trigger_below { "<" } else { ">=" }, threshold));
+    }
+}
 
-    while should_continue_monitoring(&config)
+// --------------------------------------------------------------------
+// fire_trigger - Queues the dump of a fired trigger. Returns a receiver
+// for the result of the dump.
+// --------------------------------------------------------------------
+fn fire_trigger(config: &Arc<Mutex<ProcDumpConfiguration>>, trigger: TriggerContext) -> Result<Receiver<bool>, String>
+{
+    let result = queue_dump(config, trigger);
+    if let Err(e) = &result
     {
-        // Read /proc/{pid}/stat file to get process statistics
-        let stat_path = format!("/proc/{}/stat", pid);
-        let statcontents = fs::read_to_string(stat_path).expect("Stat file not found.");
+        log(Severity::Debug, e);
+    }
 
-        // Get resident set from stat
-        let thread_count = statcontents.split(" ").nth(19).unwrap().parse::<i64>().unwrap();
+    result
+}
 
-        if thread_count >= trigger_thread_threshold.into()
-        {
-            println!("Trigger: Thread count:{} on process ID: {}", thread_count, pid);
-            write_dump(&config, &trigger_type);
-            if !should_continue_monitoring(&config)
-            {
-                // We've reached a stop state, exit
-                break;
-            }
+// --------------------------------------------------------------------
+// manual_dump - Writes a dump on request (control socket, SIGUSR1) and
+// waits for it. Manual dumps count against the number of dumps to collect.
+// --------------------------------------------------------------------
+pub fn manual_dump(config: &Arc<Mutex<ProcDumpConfiguration>>) -> Result<(), String>
+{
+    let pid = config.lock().unwrap().process_id;
 
-            // Wait for time between dumps
-            let timeout_remaining = in_between_dumps;
-            let elapsed = park_thread(timeout_remaining.into());
-            if elapsed < Duration::from_secs(in_between_dumps.into())
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
-        }
-        else
-        {
-            // Wait for polling frequency
-            let timeout_remaining = timeout;
-            let elapsed = park_thread(timeout_remaining);
-            if elapsed < Duration::from_secs(timeout)
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
-        }
+    let trigger = TriggerContext { trigger_type: "manual".to_string(), value: None, threshold: None };
+    let result = fire_trigger(config, trigger)?;
+    if !result.recv().unwrap_or(false)
+    {
+        return Err(format!("Failed to generate dump of process {}", pid));
     }
 
-    let mut lock = config.lock().unwrap();
-    lock.is_quit = true;
+    Ok(())
+}
 
-    0
+//
+// Triggers evaluated on the samples of a process
+//
+#[derive(Clone, Copy, PartialEq)]
+enum SampledTrigger
+{
+    Cpu,
+    Memory,
+    Threads,
+    FileDescriptors,
+    Timer,
+}
+
+struct TriggerState
+{
+    trigger: SampledTrigger,
+    trigger_type: &'static str,
+    threshold: u32,
+    trigger_below: bool,
+    next_evaluation: Instant,       // Triggers aren't evaluated while waiting between dumps
 }
 
 // --------------------------------------------------------------------
-// file_monitoring_thread - Monitors for file desc count  based on config
+// sampler_thread - Samples the process once per polling interval and
+// evaluates the triggers that are due on that sample. Every metric is
+// read on each sample, /proc/{pid}/stat and /proc/{pid}/fdinfo once.
 // --------------------------------------------------------------------
-pub fn file_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
+pub fn sampler_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
 {
     let lock = config.lock().unwrap();
-    let trigger_file_threshold = lock.trigger_threshold_file_descriptors;
-    let timeout = lock.polling_frequency/1000;
-    let in_between_dumps = lock.threshold_seconds;
+    let polling_interval = Duration::from_millis(lock.polling_frequency);
+    let in_between_dumps = Duration::from_secs(lock.threshold_seconds.into());
     let pid = lock.process_id;
+
+    let start = Instant::now();
+    let mut triggers = Vec::new();
+    if lock.trigger_threshold_mem != u32::MAX
+    {
+        triggers.push(TriggerState { trigger: SampledTrigger::Memory, trigger_type: "memory", threshold: lock.trigger_threshold_mem, trigger_below: lock.trigger_threshold_mem_below, next_evaluation: start });
+    }
+    if lock.trigger_threshold_cpu != u32::MAX
+    {
+        triggers.push(TriggerState { trigger: SampledTrigger::Cpu, trigger_type: "cpu", threshold: lock.trigger_threshold_cpu, trigger_below: lock.trigger_threshold_cpu_below, next_evaluation: start });
+    }
+    if lock.trigger_threshold_threads != u32::MAX
+    {
+        triggers.push(TriggerState { trigger: SampledTrigger::Threads, trigger_type: "threads", threshold: lock.trigger_threshold_threads, trigger_below: false, next_evaluation: start });
+    }
+    if lock.trigger_threshold_file_descriptors != u32::MAX
+    {
+        triggers.push(TriggerState { trigger: SampledTrigger::FileDescriptors, trigger_type: "file_descriptor", threshold: lock.trigger_threshold_file_descriptors, trigger_below: false, next_evaluation: start });
+    }
+    if lock.trigger_threshold_timer
+    {
+        // The timer fires once the first polling interval has elapsed
+        triggers.push(TriggerState { trigger: SampledTrigger::Timer, trigger_type: "timer", threshold: 0, trigger_below: false, next_evaluation: start + polling_interval });
+    }
     drop(lock);
 
-    let mut trigger_type = String::new();
-    trigger_type.push_str("file_descriptor");
+    let hz = u64::try_from(sysconf(SysconfVar::CLK_TCK).unwrap().unwrap()).unwrap();
+    let pagesize = sysconf(SysconfVar::PAGE_SIZE).unwrap().unwrap() >> 10;
 
     while should_continue_monitoring(&config)
     {
-        // Read /proc/{pid}/stat file to get process statistics
-        let stat_path = format!("/proc/{}/fdinfo", pid);
+        let now = Instant::now();
+        let mut sample = ProcessSample::default();
 
-        let paths = fs::read_dir(stat_path).unwrap();
-        let mut num_file_descriptors = 0;
-        for _ in paths
-        {
-            num_file_descriptors += 1;
-        }
-
-        if num_file_descriptors >= trigger_file_threshold
+        // Read /proc/{pid}/stat file to get process statistics
+        let stat_path = format!("/proc/{}/stat", pid);
+        let statcontents = match fs::read_to_string(stat_path)
         {
-            println!("Trigger: File descriptors:{} on process ID: {}", num_file_descriptors, pid);
-            write_dump(&config, &trigger_type);
-            if !should_continue_monitoring(&config)
+            Ok(statcontents) => statcontents,
+            Err(_) if target_exited(&config) => break,
+            Err(e) =>
             {
-                // We've reached a stop state, exit
+                log(Severity::Warning, &format!("Failed to read stat file of process {}: {}", pid, e));
+                set_process_terminated(&mut config.lock().unwrap());
                 break;
-            }
+            },
+        };
 
-            // Wait for time between dumps
-            let timeout_remaining = in_between_dumps;
-            let elapsed = park_thread(timeout_remaining.into());
-            if elapsed < Duration::from_secs(in_between_dumps.into())
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
+        // A line that can't be parsed is a failed sample
+        let stat = split_stat_fields(&statcontents).unwrap_or_default();
+        let field = |index: usize| stat.get(index).and_then(|field| field.parse::<i64>().ok());
+        if stat.is_empty()
+        {
+            log(Severity::Warning, &format!("Failed to parse stat file of process {}", pid));
         }
-        else
+
+        // Get proc stats for CPU
+        match (field(13), field(14), field(21), get_system_uptime())
         {
-            // Wait for polling frequency
-            let timeout_remaining = timeout;
-            let elapsed = park_thread(timeout_remaining);
-            if elapsed < Duration::from_secs(timeout)
+            (Some(utime), Some(stime), Some(starttime), Some(uptime)) =>
             {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
+                let total_time = (utime + stime) as u64 / hz;
+                let elapsed_time = (uptime as u64).saturating_sub(starttime as u64 / hz);
+                sample.cpu_usage = Some(if elapsed_time > 0 { ((total_time as f64 / elapsed_time as f64) * 100.0) as u32 } else { 0 });
+            },
+            (_, _, _, None) => log(Severity::Warning, "Failed to read /proc/uptime"),
+            _ => (),
         }
-    }
-
-    let mut lock = config.lock().unwrap();
-    lock.is_quit = true;
-
-    0
-}
 
-// --------------------------------------------------------------------
-// This thread monitors for a specific signal to be sent to target process.
-// It uses ptrace (PTRACE_SEIZE) and once the signal with the corresponding
-// signal number is intercepted, it detaches from the target process in a stopped state
-// followed by invoking gcore to generate the dump. Once completed, a SIGCONT followed by the
-// original signal is sent to the target process. Signals of non-interest are simply forwarded
-// to the target process.
-// --------------------------------------------------------------------
-pub fn signal_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
-{
+        // Get resident set and swap from stat
+        if let (Some(rss), Some(swap)) = (field(23), field(35))
+        {
+            sample.memory_usage = Some(((rss * pagesize) >> 10) + ((swap * pagesize) >> 10));
+        }
 
-    let mut lock = config.lock().unwrap();
-    lock.is_quit = true;
+        sample.thread_count = field(19);
 
-    0
-}
+        let fdinfo_path = format!("/proc/{}/fdinfo", pid);
+        let paths = match fs::read_dir(fdinfo_path)
+        {
+            Ok(paths) => paths,
+            Err(_) if target_exited(&config) => break,
+            Err(e) =>
+            {
+                log(Severity::Warning, &format!("Failed to read fdinfo of process {}: {}", pid, e));
+                set_process_terminated(&mut config.lock().unwrap());
+                break;
+            },
+        };
+        sample.file_descriptor_count = Some(paths.count() as u32);
 
+        // The sample is only valid if the target was still alive after it was read
+        if !should_continue_monitoring(&config)
+        {
+            break;
+        }
 
-// --------------------------------------------------------------------
-// timer_monitoring_thread - Timer based monitor  based on config
-// --------------------------------------------------------------------
-pub fn timer_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
-{
-    let lock = config.lock().unwrap();
-    let timeout = lock.polling_frequency/1000;
-    let in_between_dumps = lock.threshold_seconds;
-    let pid = lock.process_id;
-    drop(lock);
+        // Values that couldn't be read are recorded as missing rather than repeating older ones
+        record_sample(&config, |last_sample| *last_sample = sample.clone());
 
-    let mut trigger_type = String::new();
-    trigger_type.push_str("timer");
+        let paused = is_monitor_paused(&config);
+        let mut stop = false;
+        for trigger in triggers.iter_mut().filter(|trigger| trigger.next_evaluation <= now)
+        {
+            // Evaluate again on the next polling interval unless a dump is triggered
+            trigger.next_evaluation = now + polling_interval;
 
+            let value = match trigger.trigger
+            {
+                SampledTrigger::Cpu => sample.cpu_usage.map(i64::from),
+                SampledTrigger::Memory => sample.memory_usage,
+                SampledTrigger::Threads => sample.thread_count,
+                SampledTrigger::FileDescriptors => sample.file_descriptor_count.map(i64::from),
+                SampledTrigger::Timer => Some(polling_interval.as_millis() as i64),
+            };
+            let Some(value) = value else { continue };
+
+            let exceeded = if trigger.trigger == SampledTrigger::Timer
+            {
+                // The polling interval has elapsed
+                true
+            }
+            else
+            {
+                trace_sample(pid, trigger.trigger_type, value, trigger.threshold, trigger.trigger_below);
+                (trigger.trigger_below && value < trigger.threshold.into()) || (!trigger.trigger_below && value >= trigger.threshold.into())
+            };
 
-    while should_continue_monitoring(&config)
-    {
-        // Wait for polling frequency
-        let timeout_remaining = timeout;
-        let elapsed = park_thread(timeout_remaining.into());
-        if elapsed >= Duration::from_secs(timeout)
-        {
-            // Polling frequency has elapsed...generate a dump
+            // A paused monitor keeps sampling but doesn't trigger
+            if paused || !exceeded
             {
-                let lock = config.lock().unwrap();
-                println!("Trigger: Timer:{}(s) on process ID: {}", lock.polling_frequency/1000, lock.process_id);
+                continue;
             }
 
-            // Write Dump
-            println!("Trigger: Timer:{}(s) on process ID: {}", timeout, pid);
-            write_dump(&config, &trigger_type);
+            let threshold = if trigger.trigger == SampledTrigger::Timer { None } else { Some(trigger.threshold.into()) };
+            let _ = fire_trigger(&config, TriggerContext { trigger_type: trigger.trigger_type.to_string(), value: Some(value), threshold });
             if !should_continue_monitoring(&config)
             {
                 // We've reached a stop state, exit
+                stop = true;
                 break;
             }
 
-            // Wait for time between dumps
-            let timeout_remaining = in_between_dumps;
-            let elapsed = park_thread(timeout_remaining.into());
-            if elapsed < Duration::from_secs(in_between_dumps.into())
+            // Wait for time between dumps before evaluating the trigger again
+            trigger.next_evaluation = Instant::now() + in_between_dumps;
+            if trigger.trigger == SampledTrigger::Timer
             {
-                // Thread was unparked as a result of cancellation...exit
-                break;
+                trigger.next_evaluation += polling_interval;
             }
         }
-        else
+
+        if stop
+        {
+            break;
+        }
+
+        // Sample again on the next polling interval
+        if park_thread(&config, (now + polling_interval).saturating_duration_since(Instant::now()))
         {
-            // Thread was unparked as a result of cancellation...exit
+            // The monitor was cancelled...exit
             break;
         }
     }
 
     let mut lock = config.lock().unwrap();
     lock.is_quit = true;
+    log(Severity::Debug, &format!("Sampler thread for process {} exiting", lock.process_id));
 
     0
 }
 
-
 // --------------------------------------------------------------------
-// mem_monitoring_thread - Monitors for mem consumption based on config
+// This thread monitors for a specific signal to be sent to target process.
+// It uses ptrace (PTRACE_SEIZE) and once the signal with the corresponding
+// signal number is intercepted, it detaches from the target process in a stopped state
+// followed by invoking gcore to generate the dump. Once completed, a SIGCONT followed by the
+// original signal is sent to the target process. Signals of non-interest are simply forwarded
+// to the target process.
 // --------------------------------------------------------------------
-pub fn mem_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
+pub fn signal_monitoring_thread(config: Arc<Mutex<ProcDumpConfiguration>>) -> u32
 {
-    let lock = config.lock().unwrap();
-    let timeout = lock.polling_frequency/1000;
-    let in_between_dumps = lock.threshold_seconds;
-    let pid = lock.process_id;
-    let trigger_below = lock.trigger_threshold_mem_below;
-    let trigger_threshold = lock.trigger_threshold_mem;
-    drop(lock);
-
-    let mut trigger_type = String::new();
-    trigger_type.push_str("memory");
-
-    let pagesize = nix::unistd::sysconf(SysconfVar::PAGE_SIZE).unwrap().unwrap() >> 10;
-
-    while should_continue_monitoring(&config)
-    {
-        // Read /proc/{pid}/stat file to get process statistics
-        let stat_path = format!("/proc/{}/stat", pid);
-        let statcontents = fs::read_to_string(stat_path).expect("Stat file not found.");
-
-        // Get resident set from stat
-        let mut rss = statcontents.split(" ").nth(23).unwrap().parse::<i64>().unwrap();
-        rss = (rss * pagesize) >> 10;
-
-        // Get swap for stat
-        let mut swap = statcontents.split(" ").nth(35).unwrap().parse::<i64>().unwrap();
-        swap = (swap * pagesize) >> 10;
-
-        let mem_usage = rss + swap;
-
-        if (trigger_below && mem_usage < trigger_threshold.into()) || (!trigger_below && mem_usage >= trigger_threshold.into())
-        {
-            println!("Trigger: Commit usage:{}MB on process ID: {}", mem_usage, pid);
-            write_dump(&config, &trigger_type);
-            if !should_continue_monitoring(&config)
-            {
-                // We've reached a stop state, exit
-                break;
-            }
-
-            // Wait for time between dumps
-            let timeout_remaining = in_between_dumps;
-            let elapsed = park_thread(timeout_remaining.into());
-            if elapsed < Duration::from_secs(in_between_dumps.into())
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
-        }
-        else
-        {
-            // Wait for polling frequency
-            let timeout_remaining = timeout;
-            let elapsed = park_thread(timeout_remaining);
-            if elapsed < Duration::from_secs(timeout)
-            {
-                // Thread was unparked as a result of cancellation...exit
-                break;
-            }
-        }
-    }
 
     let mut lock = config.lock().unwrap();
     lock.is_quit = true;
+    log(Severity::Debug, &format!("signal monitor thread for process {} exiting", lock.process_id));
 
     0
 }
 
 // --------------------------------------------------------------------
-// park_thread - Helper function to park a thread with a timeout
+// park_thread - Parks a monitor thread for the given duration or until
+// its monitor is cancelled. Returns true if the monitor was cancelled.
 // --------------------------------------------------------------------
-pub fn park_thread(duration: u64) -> Duration
+pub fn park_thread(config: &Arc<Mutex<ProcDumpConfiguration>>, duration: Duration) -> bool
 {
-    let beginning_park = Instant::now();
-    let timeout_remaining = Duration::from_secs(duration);
-    park_timeout(timeout_remaining);
-    let elapsed = beginning_park.elapsed();
+    let deadline = Instant::now() + duration;
+    loop
+    {
+        if config.lock().unwrap().monitor_cancelled
+        {
+            return true;
+        }
+
+        // Park again after a spurious wake up
+        let now = Instant::now();
+        if now >= deadline
+        {
+            return false;
+        }
 
-    elapsed
+        park_timeout(deadline - now);
+    }
 }
\ No newline at end of file